@@ -0,0 +1,153 @@
+//! Compares the subsets mkwebfont generates for Google Fonts families against the
+//! `unicode-range`s served by the Google Fonts CSS API, and reports where they diverge.
+//!
+//! This is used to check that the bundled subset data is current, and that the splitter produces
+//! partitions close to the ones used by Google Fonts.
+//!
+//! Usage: `cargo run --example compare_gfonts_css -- "Noto Sans" Roboto`
+
+use anyhow::{bail, ensure, Result};
+use mkwebfont::{FontStyle, LoadedFontSetBuilder, SplitterPlan};
+use mkwebfont_common::character_set::CharacterSet;
+use std::io;
+use tracing::{info, warn};
+
+/// A browser user agent, as the Google Fonts API only serves `unicode-range` to modern browsers.
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+fn parse_unicode_range(ranges: &str) -> Result<CharacterSet> {
+    let mut chars = CharacterSet::new();
+    for range in ranges.split(',') {
+        let Some(range) = range.trim().strip_prefix("U+") else {
+            bail!("unicode-range does not start with `U+`: {range}");
+        };
+        let (start, end) = if let Some((start, end)) = range.split_once('-') {
+            (u32::from_str_radix(start, 16)?, u32::from_str_radix(end, 16)?)
+        } else if range.contains('?') {
+            let start = u32::from_str_radix(&range.replace('?', "0"), 16)?;
+            let end = u32::from_str_radix(&range.replace('?', "F"), 16)?;
+            (start, end)
+        } else {
+            let val = u32::from_str_radix(range, 16)?;
+            (val, val)
+        };
+        chars.extend(start..=end);
+    }
+    Ok(chars)
+}
+
+/// Returns the subsets Google Fonts serves for the regular style of a family.
+fn fetch_gfonts_subsets(family: &str) -> Result<Vec<(String, CharacterSet)>> {
+    let url = format!("https://fonts.googleapis.com/css2?family={}", family.replace(' ', "+"));
+    let css = ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .call()?
+        .into_string()?;
+
+    let mut subsets: Vec<(String, CharacterSet)> = Vec::new();
+    let mut name = None;
+    for line in css.lines() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix("/*").and_then(|x| x.strip_suffix("*/")) {
+            name = Some(comment.trim().to_string());
+        } else if let Some(range) = line.strip_prefix("unicode-range:") {
+            let name = name
+                .take()
+                .unwrap_or_else(|| format!("unnamed{}", subsets.len()));
+            subsets.push((name, parse_unicode_range(range.trim().trim_end_matches(';'))?));
+        }
+    }
+    ensure!(!subsets.is_empty(), "Google Fonts returned no `unicode-range` for {family}.");
+    Ok(subsets)
+}
+
+/// Returns the subsets mkwebfont generates for the regular style of a family.
+async fn generate_subsets(family: &str) -> Result<Vec<(String, CharacterSet)>> {
+    let fonts = LoadedFontSetBuilder::new()
+        .load_from_gfonts([family])
+        .build()
+        .await?;
+    let mut plan = SplitterPlan::new();
+    plan.gfonts_splitter();
+    let results = mkwebfont::process_webfont(&plan, &fonts, None).await?;
+
+    let Some(font) = results.webfonts.iter().find(|x| {
+        x.font_family().eq_ignore_ascii_case(family)
+            && x.parsed_font_style() == FontStyle::Regular
+            && x.weight_range().contains(&400)
+    }) else {
+        bail!("No regular style was generated for {family}.");
+    };
+    Ok(font
+        .subsets()
+        .iter()
+        .map(|x| (x.name().to_string(), x.subset().clone()))
+        .collect())
+}
+
+/// Compares the partitions for one family, returning whether they match exactly.
+async fn compare_family(family: &str) -> Result<bool> {
+    let expected = fetch_gfonts_subsets(family)?;
+    let generated = generate_subsets(family).await?;
+
+    let mut covered = CharacterSet::new();
+    for (_, chars) in &generated {
+        covered |= chars;
+    }
+
+    let mut matching = 0;
+    let mut compared = 0;
+    for (name, chars) in &expected {
+        // only the characters the font actually contains can be compared
+        let chars = chars.clone() & &covered;
+        if chars.is_empty() {
+            continue;
+        }
+        compared += 1;
+
+        let (best_name, best_chars) = generated
+            .iter()
+            .max_by_key(|(_, x)| (x.clone() & &chars).len())
+            .unwrap();
+        let overlap = (best_chars.clone() & &chars).len();
+        let spread = generated
+            .iter()
+            .filter(|(_, x)| x.intersects(&chars))
+            .count();
+        if overlap == chars.len() && overlap == best_chars.len() {
+            matching += 1;
+            info!("{family} / {name}: matches '{best_name}' exactly ({overlap} codepoints)");
+        } else {
+            warn!(
+                "{family} / {name}: {overlap} of {} codepoints in '{best_name}' ({} codepoints), \
+                 spread over {spread} subsets",
+                chars.len(),
+                best_chars.len(),
+            );
+        }
+    }
+    info!("{family}: {matching} of {compared} Google Fonts subsets match exactly.");
+    Ok(matching == compared)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("info")
+        .with_writer(io::stderr)
+        .init();
+
+    let families: Vec<_> = std::env::args().skip(1).collect();
+    ensure!(!families.is_empty(), "At least one Google Fonts family must be given.");
+
+    let mut diverged = Vec::new();
+    for family in &families {
+        if !compare_family(family).await? {
+            diverged.push(family.as_str());
+        }
+    }
+    if !diverged.is_empty() {
+        bail!("Subsets diverge from Google Fonts for: {}", diverged.join(", "));
+    }
+    Ok(())
+}
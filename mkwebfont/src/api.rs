@@ -3,33 +3,40 @@ use crate::{
     splitter,
     splitter::FALLBACK_FONT_NAME,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use arcstr::ArcStr;
+use enumset::EnumSet;
 use mkwebfont_common::{
-    character_set::CharacterSet,
-    download_cache::DownloadInfo,
+    character_set::{CharacterSet, CompressedCharacterSet},
+    download_cache::{download_url, DownloadInfo},
     hashing::{WyHashMap, WyHashSet},
     join_set::JoinSet,
+    paths::get_relative_from,
 };
 use mkwebfont_extract_web::{RewriteContext, WebrootInfo, WebrootInfoExtractor};
 use mkwebfont_fontops::{
-    font_info::{FontFaceSet, FontFaceWrapper},
+    font_info::{self, FontFaceSet, FontFaceWrapper, WeightNames},
     gfonts::gfonts_list::GfontsList,
+    subsetter::dedup_subset_files,
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashSet},
     fmt::Debug,
     ops::RangeInclusive,
     path::{Path, PathBuf},
     sync::Arc,
 };
-use tracing::{info, info_span, Instrument};
+use tracing::{info, info_span, warn, Instrument};
 
 use crate::plan::LoadedSplitterPlan;
 pub use crate::plan::SplitterPlan;
+pub use mkwebfont_common::download_cache::set_download_concurrency;
+pub use mkwebfont_extract_web::{
+    warnings, ExtractorConfig, ExtractorFlags, FontDisplay, GenericFamilies, RenderWebfontCss,
+};
 pub use mkwebfont_fontops::{
-    font_info::{FontStyle, FontWeight},
-    subsetter::{SubsetInfo, WebfontInfo},
+    font_info::{parse_axis_name, AxisName, FontStyle, FontWeight, SubsetSettings},
+    subsetter::{set_encode_concurrency, SubsetInfo, WebfontInfo},
 };
 use serde::{Deserialize, Serialize};
 
@@ -84,6 +91,51 @@ impl LoadedFont {
     pub fn is_variable(&self) -> bool {
         self.underlying.is_variable()
     }
+
+    /// Returns whether the font is monospaced, according to its `post` table or PANOSE
+    /// classification.
+    pub fn is_monospace(&self) -> bool {
+        self.underlying.classification().is_monospace
+    }
+
+    /// Returns whether the font has serifs, according to its PANOSE classification or IBM font
+    /// class. Returns `None` if the font does not specify either.
+    pub fn is_serif(&self) -> Option<bool> {
+        self.underlying.classification().is_serif
+    }
+
+    /// Returns whether the embedding permissions (`fsType`) of the font allow it to be embedded
+    /// in web pages.
+    pub fn embeddable(&self) -> bool {
+        self.underlying.classification().embeddable()
+    }
+
+    /// Returns a minimal .woff2 file containing only the characters of `text` present in the font.
+    ///
+    /// This is meant for rendering previews of a font.
+    pub fn subset_preview(&self, text: &str) -> Result<Vec<u8>> {
+        let mut chars = CharacterSet::new();
+        for ch in text.chars() {
+            chars.insert(ch as u32);
+        }
+        let chars = self.underlying.codepoints_in_set(&chars);
+        self.underlying
+            .subset("preview", &chars, &SubsetSettings::default())
+    }
+
+    /// Subsets the font to the given characters, returning the subset as a .woff2 file.
+    ///
+    /// `name` is stored in the .woff2 file's metadata. Characters not present in the font are
+    /// ignored. This performs no splitting, and is meant for callers that handle splitting and
+    /// CSS generation themselves.
+    pub fn subset_to_woff2(
+        &self,
+        name: &str,
+        chars: &CharacterSet,
+        settings: &SubsetSettings,
+    ) -> Result<Vec<u8>> {
+        self.underlying.subset(name, chars, settings)
+    }
 }
 
 /// The builder for a set of loaded fonts.
@@ -91,8 +143,12 @@ impl LoadedFont {
 pub struct LoadedFontSetBuilder {
     fonts: Vec<LoadedFont>,
     paths: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
     gfonts: Vec<String>,
+    urls: Vec<String>,
     webroot: Option<Webroot>,
+    max_webroot_downloads: Option<usize>,
+    weight_names: WeightNames,
 }
 impl LoadedFontSetBuilder {
     /// Creates a new empty builder.
@@ -107,6 +163,13 @@ impl LoadedFontSetBuilder {
         self
     }
 
+    /// Loads all fonts found recursively in the given directories.
+    pub fn load_from_dir(mut self, dirs: impl IntoIterator<Item = impl AsRef<Path>>) -> Self {
+        self.dirs
+            .extend(dirs.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
     /// Loads fonts from the Google Fonts repository.
     ///
     /// This does *NOT* use the Google Fonts service, but rather the repository on Github!
@@ -116,6 +179,17 @@ impl LoadedFontSetBuilder {
         self
     }
 
+    /// Loads fonts from HTTP(S) URLs.
+    ///
+    /// Downloaded fonts are cached, and only downloaded again when the server reports that they
+    /// changed. URLs that cannot be downloaded or do not contain a valid font are skipped with a
+    /// warning.
+    pub fn load_from_urls(mut self, urls: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.urls
+            .extend(urls.into_iter().map(|x| x.as_ref().to_string()));
+        self
+    }
+
     /// Loads the fonts required for a given webroot.
     pub fn add_from_webroot(mut self, webroot: &Webroot) -> Self {
         assert!(self.webroot.is_none());
@@ -123,6 +197,24 @@ impl LoadedFontSetBuilder {
         self
     }
 
+    /// Limits the number of font files that may be downloaded from Google Fonts for a webroot.
+    ///
+    /// If the webroot requires more files than this, building the font set fails instead. This
+    /// protects against accidentally downloading a huge number of fonts for a large or malformed
+    /// webroot.
+    pub fn max_webroot_downloads(mut self, limit: usize) -> Self {
+        self.max_webroot_downloads = Some(limit);
+        self
+    }
+
+    /// Sets the weight for a custom weight name (such as `Book`) in the style names of fonts,
+    /// used for fonts without an OS/2 weight class. `Book` (380) and `Text` (400) are known by
+    /// default.
+    pub fn weight_name(mut self, name: &str, weight: u32) -> Self {
+        self.weight_names.insert(name, weight);
+        self
+    }
+
     /// Adds a font to the font set buidler.
     pub fn add_font(mut self, font: LoadedFont) -> Self {
         self.fonts.push(font);
@@ -148,37 +240,96 @@ impl LoadedFontSetBuilder {
     }
 
     /// Merges two font set builders.
-    pub fn merge(mut self, other: LoadedFontSetBuilder) {
+    ///
+    /// If both builders load fonts for a webroot, the webroot of this builder is used.
+    pub fn merge(mut self, other: LoadedFontSetBuilder) -> Self {
         self.fonts.extend(other.fonts);
         self.paths.extend(other.paths);
+        self.dirs.extend(other.dirs);
         self.gfonts.extend(other.gfonts);
+        self.urls.extend(other.urls);
+        for (name, weight) in other.weight_names.iter() {
+            self.weight_names.insert(name, weight);
+        }
+        if self.webroot.is_none() {
+            self.webroot = other.webroot;
+        }
+        if self.max_webroot_downloads.is_none() {
+            self.max_webroot_downloads = other.max_webroot_downloads;
+        }
+        self
     }
 
     /// Builds the final font set.
-    pub async fn build(self) -> Result<LoadedFontSet> {
-        let mut joins = JoinSet::new();
+    pub async fn build(mut self) -> Result<LoadedFontSet> {
+        if !self.dirs.is_empty() {
+            let mut visited = WyHashSet::default();
+            for dir in &self.dirs {
+                find_fonts_in_dir(dir, &mut visited, &mut self.paths)?;
+            }
+        }
+
+        let mut disk_joins = JoinSet::new();
         if !self.paths.is_empty() {
             let paths = self.paths;
-            joins.spawn(load_fonts_from_disk(paths));
+            disk_joins.spawn(load_fonts_from_disk(paths));
         }
+        let mut gfonts_joins = JoinSet::new();
         if !self.gfonts.is_empty() {
             let gfonts = self.gfonts;
-            joins.spawn(load_fonts_from_gfonts(gfonts));
+            gfonts_joins.spawn(load_fonts_from_gfonts(gfonts));
+        }
+        let mut url_joins = JoinSet::new();
+        if !self.urls.is_empty() {
+            let urls = self.urls;
+            url_joins.spawn(load_fonts_from_urls(urls));
         }
 
+        let weight_names = self.weight_names;
         let mut fonts = Vec::new();
-        fonts.extend(joins.join_vec().await?);
+        fonts.extend(disk_joins.join_vec().await?);
+        fonts.extend(url_joins.join_vec().await?);
         fonts.extend(self.fonts);
+        let mut downloaded = gfonts_joins.join_vec().await?;
+        fonts.extend(downloaded.iter().cloned());
+        for font in &mut fonts {
+            font.underlying = font.underlying.with_weight_names(&weight_names);
+        }
 
         if let Some(webroot) = self.webroot {
             info!("Resolving remaining webroot fonts...");
             let font_set = FontFaceSet::build(fonts.iter().map(|x| x.underlying.clone()));
-            fonts.extend(load_fonts_from_webroot(webroot, font_set).await?);
+            let webroot_fonts =
+                load_fonts_from_webroot(webroot, font_set, self.max_webroot_downloads).await?;
+            fonts.extend(
+                webroot_fonts.iter().map(|x| LoadedFont {
+                    underlying: x.underlying.with_weight_names(&weight_names),
+                }),
+            );
+            downloaded.extend(webroot_fonts);
         }
 
+        let gfonts_summary = (!downloaded.is_empty()).then(|| {
+            let info = GfontsList::load();
+            let mut fonts: Vec<_> = downloaded
+                .iter()
+                .map(|x| GfontsDownload {
+                    family: x.font_family().to_string(),
+                    style: x.font_style().to_string(),
+                    version: x.font_version().to_string(),
+                })
+                .collect();
+            fonts.sort_by(|a, b| (&a.family, &a.style).cmp(&(&b.family, &b.style)));
+            GfontsSummary {
+                repo_revision: info.repo_revision.clone(),
+                repo_date: info.repo_date.clone(),
+                fonts,
+            }
+        });
+
         let font_set = FontFaceSet::build(fonts.into_iter().map(|x| x.underlying));
         info!("{} total fonts loaded!", font_set.as_list().len());
-        Ok(LoadedFontSet { font_set })
+        Ok(LoadedFontSet { font_set, gfonts_summary })
     }
 }
 
@@ -187,8 +338,15 @@ impl LoadedFontSetBuilder {
 /// Create these with [`LoadedFontSetBuilder`].
 pub struct LoadedFontSet {
     font_set: FontFaceSet,
+    gfonts_summary: Option<GfontsSummary>,
 }
 impl LoadedFontSet {
+    /// Returns the fonts that were downloaded from the Google Fonts repository, and the revision
+    /// of the repository they were taken from. Returns `None` if no fonts were downloaded.
+    pub fn gfonts_summary(&self) -> Option<&GfontsSummary> {
+        self.gfonts_summary.as_ref()
+    }
+
     /// Retrieves a font by name.
     pub fn resolve(&self, name: &str) -> Result<Vec<LoadedFont>> {
         Ok(self
@@ -200,9 +358,18 @@ impl LoadedFontSet {
     }
 
     /// Dumps all fonts in this set to a given directory.
-    pub fn dump_fonts(&self, target: &Path, plan: &LoadedSplitterPlan) -> Result<FontDumpInfo> {
+    ///
+    /// If `include_codepoints` is set, the full codepoint coverage of each font is included in the
+    /// output, in addition to the codepoint count.
+    pub fn dump_fonts(
+        &self,
+        target: &Path,
+        plan: &LoadedSplitterPlan,
+        include_codepoints: bool,
+    ) -> Result<FontDumpInfo> {
         std::fs::create_dir_all(target)?;
-        let mut dump = FontDumpInfo { font_faces: Default::default() };
+        let mut dump =
+            FontDumpInfo { font_faces: Default::default(), gfonts: self.gfonts_summary.clone() };
         for font in self.font_set.as_list() {
             if plan.family_config.check_font(font) {
                 let name = format!(
@@ -224,6 +391,12 @@ impl LoadedFontSet {
                         weight_num: font.parsed_font_weight().as_num(),
                         weight_range: font.weight_range(),
                         is_variable: font.is_variable(),
+                        codepoint_count: font.all_codepoints().len(),
+                        codepoints: if include_codepoints {
+                            Some(font.all_codepoints().compressed())
+                        } else {
+                            None
+                        },
                         name,
                     });
             } else {
@@ -236,7 +409,25 @@ impl LoadedFontSet {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FontDumpInfo {
-    font_faces: HashMap<String, Vec<FontDumpFile>>,
+    font_faces: BTreeMap<String, Vec<FontDumpFile>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gfonts: Option<GfontsSummary>,
+}
+
+/// The fonts downloaded from the Google Fonts repository during a run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GfontsSummary {
+    pub repo_revision: String,
+    pub repo_date: String,
+    pub fonts: Vec<GfontsDownload>,
+}
+
+/// A font file downloaded from the Google Fonts repository.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GfontsDownload {
+    pub family: String,
+    pub style: String,
+    pub version: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -247,6 +438,9 @@ pub struct FontDumpFile {
     weight_num: u32,
     weight_range: RangeInclusive<u32>,
     is_variable: bool,
+    codepoint_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codepoints: Option<CompressedCharacterSet>,
     name: String,
 }
 
@@ -254,6 +448,7 @@ pub struct FontDumpFile {
 async fn load_fonts_from_webroot(
     webroot: Webroot,
     existing: FontFaceSet,
+    max_downloads: Option<usize>,
 ) -> Result<Vec<LoadedFont>> {
     fn check_font(
         existing: &FontFaceSet,
@@ -293,6 +488,14 @@ async fn load_fonts_from_webroot(
         }
     }
 
+    if let Some(limit) = max_downloads {
+        ensure!(
+            infos.len() <= limit,
+            "The webroot requires {} font files from Google Fonts, which exceeds the limit of {limit}.",
+            infos.len(),
+        );
+    }
+
     let mut joins = JoinSet::new();
     for info in infos {
         joins.spawn(async move {
@@ -306,6 +509,16 @@ async fn load_fonts_from_webroot(
     Ok(fonts)
 }
 
+/// Returns the full commit hash of the Google Fonts repository revision bundled with mkwebfont.
+pub fn gfonts_revision() -> &'static str {
+    &GfontsList::load().repo_revision
+}
+
+/// Returns the date of the Google Fonts repository revision bundled with mkwebfont.
+pub fn gfonts_revision_date() -> &'static str {
+    &GfontsList::load().repo_date
+}
+
 /// A fast function for loading fonts from Google Fonts.
 async fn load_fonts_from_gfonts(
     names: impl IntoIterator<Item = impl AsRef<str>>,
@@ -337,6 +550,36 @@ async fn load_fonts_from_gfonts(
     Ok(fonts)
 }
 
+/// Recursively finds all font files in a directory, skipping directories already visited.
+fn find_fonts_in_dir(
+    dir: &Path,
+    visited: &mut WyHashSet<PathBuf>,
+    out: &mut Vec<PathBuf>,
+) -> Result<()> {
+    const EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "woff", "woff2"];
+
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|x| Ok(x?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_fonts_in_dir(&path, visited, out)?;
+        } else if path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .is_some_and(|x| EXTENSIONS.contains(&x.as_str()))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
 /// A fast function for loading fonts from disk.
 async fn load_fonts_from_disk(
     paths: impl IntoIterator<Item = impl AsRef<Path>>,
@@ -355,34 +598,274 @@ async fn load_fonts_from_disk(
     Ok(fonts)
 }
 
+/// A function for loading fonts from HTTP(S) URLs. URLs that fail to load are skipped.
+async fn load_fonts_from_urls(
+    urls: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Result<Vec<LoadedFont>> {
+    let mut joins = JoinSet::new();
+    for url in urls {
+        let url = url.as_ref().to_string();
+        joins.spawn(async move {
+            info!("Loading font: (URL) {url}");
+            let fonts = match download_url(&url).await {
+                Ok(data) => {
+                    let name = url.rsplit('/').next().map(str::to_string);
+                    FontFaceWrapper::load(name, data)
+                }
+                Err(e) => Err(e),
+            };
+            match fonts {
+                Ok(fonts) => Ok(fonts
+                    .into_iter()
+                    .map(|x| LoadedFont { underlying: x })
+                    .collect()),
+                Err(e) => {
+                    warn!("Could not load font from '{url}': {e}");
+                    Ok(Vec::new())
+                }
+            }
+        });
+    }
+
+    let fonts = joins.join_vec().await?;
+    info!("Loaded {} font files from URLs...", fonts.len());
+    Ok(fonts)
+}
+
 #[derive(Debug, Clone)]
 pub struct Webroot(Arc<WebrootInfo>);
 impl Webroot {
     pub async fn load(path: &Path) -> Result<Webroot> {
-        let extractor = WebrootInfoExtractor::new();
+        Self::load_with_flags(path, EnumSet::empty()).await
+    }
+
+    pub async fn load_with_flags(path: &Path, flags: EnumSet<ExtractorFlags>) -> Result<Webroot> {
+        Self::load_with_config(path, &ExtractorConfig::new(flags)).await
+    }
+
+    pub async fn load_with_config(path: &Path, config: &ExtractorConfig) -> Result<Webroot> {
+        let extractor = WebrootInfoExtractor::with_config(config.clone());
         extractor.push_webroot(path, &[]).await?;
         Ok(Webroot(Arc::new(extractor.build().await)))
     }
 
+    /// Loads a webroot, reusing the analysis stored in a cache file if no HTML or CSS file in the
+    /// webroot has changed since it was written. Otherwise, the webroot is processed and the cache
+    /// file is updated.
+    pub async fn load_cached(
+        path: &Path,
+        config: &ExtractorConfig,
+        cache: &Path,
+    ) -> Result<Webroot> {
+        if let Some(info) = WebrootInfo::load_cache(cache, path, config)? {
+            info!("Loaded webroot analysis from '{}'.", cache.display());
+            return Ok(Webroot(Arc::new(info)));
+        }
+        let webroot = Self::load_with_config(path, config).await?;
+        info!("Writing webroot analysis to '{}'...", cache.display());
+        webroot.0.save_cache(cache, path, config)?;
+        Ok(webroot)
+    }
+
     pub async fn rewrite_webroot(&self, ctx: RewriteContext) -> Result<()> {
         self.0.rewrite_webroot(ctx).await
     }
+
+    /// Returns the characters used by each font stack in the webroot, without loading any fonts.
+    pub fn font_stack_usage(&self) -> Vec<FontStackUsage> {
+        let mut usage: Vec<_> = self
+            .0
+            .font_stacks
+            .iter()
+            .map(|stack| {
+                let mut chars = CharacterSet::new();
+                for ch in stack.glyphs().chars() {
+                    chars.insert(ch as u32);
+                }
+                FontStackUsage {
+                    stack: stack.stack.iter().map(|x| x.to_string()).collect(),
+                    codepoint_count: chars.len(),
+                    unicode_range: format_unicode_range(&chars),
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| a.stack.cmp(&b.stack));
+        usage
+    }
+}
+
+/// The characters used by a font stack in a webroot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FontStackUsage {
+    pub stack: Vec<String>,
+    pub codepoint_count: usize,
+    /// The characters used, in the same format as `unicode-range` in CSS.
+    pub unicode_range: String,
+}
+
+fn format_unicode_range(chars: &CharacterSet) -> String {
+    let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    for ch in chars.iter_sorted() {
+        match ranges.last_mut() {
+            Some(range) if *range.end() + 1 == ch => *range = *range.start()..=ch,
+            _ => ranges.push(ch..=ch),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|x| {
+            if x.start() == x.end() {
+                format!("U+{:X}", x.start())
+            } else {
+                format!("U+{:X}-{:X}", x.start(), x.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
+/// The files of a store held in memory, as pairs of file names and file contents.
+pub type MemoryStoreFiles = Vec<(String, Vec<u8>)>;
+
 #[derive(Clone, Debug)]
 pub struct WebfontResults {
     pub webfonts: Vec<Arc<WebfontInfo>>,
+    plan: LoadedSplitterPlan,
     fallback_info: WyHashMap<Arc<[ArcStr]>, CharacterSet>,
     webroot: Option<Webroot>,
+    assigned: Arc<AssignedSubsets>,
+    gfonts_summary: Option<GfontsSummary>,
 }
 impl WebfontResults {
-    fn rewrite_ctx(&self, store_path: PathBuf, store_uri: Option<String>) -> RewriteContext {
+    fn rewrite_ctx(
+        &self,
+        store_path: PathBuf,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> RewriteContext {
         RewriteContext {
             fallback_font_name: FALLBACK_FONT_NAME.to_string(),
             fallback_info: self.fallback_info.clone(),
             webfonts: self.webfonts.clone(),
             store_path,
-            store_uri,
+            store_uris: store_uris
+                .into_iter()
+                .map(|x| x.as_ref().to_string())
+                .collect(),
+            emit_metric_overrides: self.plan.flags.contains(FontFlags::MetricOverrides),
+            omit_format_hint: self.plan.flags.contains(FontFlags::NoFormatHint),
+            minify_css: !self.plan.flags.contains(FontFlags::NoMinifyCss),
+            inline_below: self.plan.inline_below,
+            family_suffix: self.plan.family_suffix.as_deref().map(str::to_string),
+            family_media: self.plan.family_media.clone(),
+            font_display: self.plan.font_display,
+            preload_links: self.plan.flags.contains(FontFlags::PreloadLinks),
+            preload_integrity: self.plan.flags.contains(FontFlags::PreloadIntegrity),
+            dry_run: self.plan.flags.contains(FontFlags::DryRun),
+        }
+    }
+
+    /// Returns whether a character is contained in any generated subset, including the subsets of
+    /// the fallback font.
+    pub fn covers(&self, ch: char) -> bool {
+        self.webfonts
+            .iter()
+            .flat_map(|x| x.subsets())
+            .any(|x| x.subset().contains(ch as u32))
+    }
+
+    /// Finds text in the webroot that would be displayed with a system font, as no font in its
+    /// font stack (including the fallback font) covers some of its characters.
+    pub fn find_tofu(&self) -> Vec<TofuReport> {
+        let Some(webroot) = &self.webroot else {
+            return Vec::new();
+        };
+
+        let mut reports = Vec::new();
+        for stack in &webroot.0.font_stacks {
+            let mut covered = CharacterSet::new();
+            for font in &self.webfonts {
+                let in_stack = if font.font_family() == FALLBACK_FONT_NAME {
+                    self.fallback_info.contains_key(&stack.stack)
+                } else {
+                    let family = font.font_family().to_lowercase();
+                    stack.stack.iter().any(|x| x.as_str() == family)
+                };
+                if in_stack {
+                    for subset in font.subsets() {
+                        covered.extend(subset.subset());
+                    }
+                }
+            }
+
+            for sample in &stack.samples {
+                for text in &sample.content {
+                    let missing: String = text
+                        .chars()
+                        .filter(|x| !x.is_whitespace() && !covered.contains(*x as u32))
+                        .collect();
+                    if !missing.is_empty() {
+                        reports.push(TofuReport {
+                            stack: stack.stack.iter().map(|x| x.to_string()).collect(),
+                            text: text.to_string(),
+                            missing,
+                        });
+                    }
+                }
+            }
+        }
+        reports
+    }
+
+    /// Traces a single character through the pipeline, explaining which fonts cover it, which
+    /// font stacks use it, and which generated subsets it ended up in.
+    pub fn explain(&self, fonts: &LoadedFontSet, ch: char) -> CharacterExplanation {
+        let codepoint = ch as u32;
+
+        let mut font_stacks = Vec::new();
+        if let Some(webroot) = &self.webroot {
+            for stack in &webroot.0.font_stacks {
+                let is_used = stack
+                    .samples
+                    .iter()
+                    .any(|x| x.content.iter().any(|x| x.contains(ch)));
+                if is_used {
+                    font_stacks.push(stack.stack.iter().map(|x| x.to_string()).collect());
+                }
+            }
+        }
+
+        let mut covering_fonts = Vec::new();
+        for font in fonts.font_set.as_list() {
+            if font.all_codepoints().contains(codepoint) {
+                covering_fonts.push(CharacterFontInfo {
+                    font: font.to_string(),
+                    processed: self.plan.family_config.check_font(font),
+                    used: self.assigned.get_used_chars(font).contains(codepoint),
+                    preloaded: self.assigned.get_preload_chars(font).contains(codepoint),
+                });
+            }
+        }
+
+        let mut subsets = Vec::new();
+        for font in &self.webfonts {
+            for subset in font.subsets() {
+                if subset.subset().contains(codepoint) {
+                    subsets.push(CharacterSubsetInfo {
+                        family: font.font_family().to_string(),
+                        style: font.font_style().to_string(),
+                        subset: subset.name().to_string(),
+                        file: subset.woff2_file_name().to_string(),
+                    });
+                }
+            }
+        }
+
+        CharacterExplanation {
+            codepoint: format!("U+{codepoint:04X}"),
+            font_stacks,
+            covering_fonts,
+            needs_fallback: self.assigned.get_fallback_chars().contains(codepoint),
+            subsets,
         }
     }
 
@@ -390,15 +873,16 @@ impl WebfontResults {
         self.webroot.is_some()
     }
 
+    /// Rewrites the webroot to use the generated webfonts.
+    ///
+    /// If multiple store URIs are given, the generated files are distributed between them by file
+    /// name.
     pub async fn rewrite_webroot(
         &self,
         store_path: impl AsRef<Path>,
-        store_uri: Option<impl AsRef<str>>,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
     ) -> Result<()> {
-        let rewrite_ctx = self.rewrite_ctx(
-            store_path.as_ref().to_path_buf(),
-            store_uri.map(|x| x.as_ref().to_string()),
-        );
+        let rewrite_ctx = self.rewrite_ctx(store_path.as_ref().to_path_buf(), store_uris);
         if let Some(webroot) = &self.webroot {
             webroot.rewrite_webroot(rewrite_ctx).await
         } else {
@@ -406,27 +890,425 @@ impl WebfontResults {
         }
     }
 
+    /// Generates the `@font-face` CSS for the generated webfonts.
+    ///
+    /// If multiple store URIs are given, the generated files are distributed between them by file
+    /// name.
     pub fn produce_css(
         &self,
         store_path: impl AsRef<Path>,
-        store_uri: Option<impl AsRef<str>>,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
     ) -> Result<String> {
-        if store_uri.is_none() {
+        let rewrite_ctx = self.rewrite_ctx(store_path.as_ref().to_path_buf(), store_uris);
+        if rewrite_ctx.store_uris.is_empty() {
             bail!("Cannot generate detached .css files without an explicit store URI.")
         }
-        let rewrite_ctx = self.rewrite_ctx(
-            store_path.as_ref().to_path_buf(),
-            Some(store_uri.unwrap().as_ref().to_string()),
-        );
         rewrite_ctx.generate_font_css()
     }
 
+    /// Generates the `@font-face` CSS for the generated webfonts, for a .css file at the given
+    /// path.
+    ///
+    /// If no store URIs are given, the store is referenced by its path relative to the .css file.
+    /// The store and the directory containing the .css file must already exist.
+    pub fn produce_css_at(
+        &self,
+        css_path: impl AsRef<Path>,
+        store_path: impl AsRef<Path>,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<String> {
+        let mut store_uris: Vec<_> = store_uris
+            .into_iter()
+            .map(|x| x.as_ref().to_string())
+            .collect();
+        if store_uris.is_empty() {
+            let uri = get_relative_from(css_path.as_ref(), store_path.as_ref())?;
+            info!("Using store URI '{uri}', relative to '{}'.", css_path.as_ref().display());
+            store_uris.push(uri.to_string());
+        }
+        self.produce_css(store_path, store_uris)
+    }
+
+    /// Generates `<link rel="preload">` tags for the base subset of each font family, for
+    /// inclusion in the `<head>` of pages using the generated CSS.
+    ///
+    /// Only one style is preloaded per family, preferring the regular style at weight 400.
+    pub fn produce_preload_links(&self, store_uri: &str) -> Result<Vec<String>> {
+        self.rewrite_ctx(PathBuf::new(), [store_uri])
+            .generate_preload_links()
+    }
+
     pub fn write_webfonts(&self, store_path: impl AsRef<Path>) -> Result<()> {
         for font in &self.webfonts {
             font.write_to_store(store_path.as_ref())?;
         }
         Ok(())
     }
+
+    /// Returns the generated files as pairs of file names and contents, alongside the
+    /// `@font-face` CSS referencing them from the given store URI. Nothing is written to disk,
+    /// so the files can be served directly from memory.
+    pub fn into_memory_store(self, store_uri: &str) -> Result<(MemoryStoreFiles, String)> {
+        let css = self.produce_css(PathBuf::new(), [store_uri])?;
+
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
+        for subset in self.webfonts.iter().flat_map(|x| x.subsets()) {
+            for (file_name, data) in subset.files() {
+                if seen.insert(file_name) {
+                    files.push((file_name.to_string(), data.to_vec()));
+                }
+            }
+        }
+        Ok((files, css))
+    }
+
+    /// Returns an index mapping each generated subset file to the codepoints it covers.
+    ///
+    /// This allows a client-side loader to fetch exactly the subset containing a given character,
+    /// without relying on `unicode-range` in CSS.
+    pub fn subset_index(&self) -> Vec<SubsetIndexEntry> {
+        let mut index = Vec::new();
+        for font in &self.webfonts {
+            let weight = font.weight_range();
+            for subset in font.subsets() {
+                index.push(SubsetIndexEntry {
+                    family: font.font_family().to_string(),
+                    style: font.font_style().to_string(),
+                    weight: [*weight.start(), *weight.end()],
+                    file: subset.woff2_file_name().to_string(),
+                    ranges: subset
+                        .unicode_ranges()
+                        .iter()
+                        .map(|x| [*x.start(), *x.end()])
+                        .collect(),
+                });
+            }
+        }
+        index
+    }
+
+    /// Returns a description of every generated webfont and its subsets.
+    pub fn manifest(&self) -> WebfontManifest {
+        let mut manifest =
+            WebfontManifest { font_faces: BTreeMap::new(), gfonts: self.gfonts_summary.clone() };
+        for font in &self.webfonts {
+            let subsets = font
+                .subsets()
+                .iter()
+                .map(|subset| ManifestSubset {
+                    name: subset.name().to_string(),
+                    file: subset.woff2_file_name().to_string(),
+                    size: subset.woff2_data().len(),
+                    integrity: subset.integrity().to_string(),
+                    woff_file: subset.woff_file_name().map(str::to_string),
+                    woff_size: subset.woff_data().map(|x| x.len()),
+                    woff_integrity: subset.woff_integrity().map(str::to_string),
+                    codepoint_count: subset.subset().len(),
+                    unicode_ranges: subset
+                        .unicode_ranges()
+                        .iter()
+                        .map(|x| [*x.start(), *x.end()])
+                        .collect(),
+                })
+                .collect();
+            manifest
+                .font_faces
+                .entry(font.font_family().to_string())
+                .or_default()
+                .push(ManifestFontFace {
+                    style_name: font.font_style().to_string(),
+                    style: format!("{:?}", font.parsed_font_style()),
+                    weight_range: font.weight_range(),
+                    subsets,
+                });
+        }
+        manifest
+    }
+
+    /// Writes the manifest returned by [`WebfontResults::manifest`] to a file as JSON.
+    pub fn write_manifest(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        info!("Writing manifest to '{}'...", path.display());
+        std::fs::write(path, serde_json::to_string_pretty(&self.manifest())?)?;
+        Ok(())
+    }
+
+    /// Writes the subset index returned by [`WebfontResults::subset_index`] into the store, as
+    /// `subset-index.json`.
+    pub fn write_subset_index(&self, store_path: impl AsRef<Path>) -> Result<()> {
+        let target = store_path.as_ref().join("subset-index.json");
+        info!("Writing subset index to '{}'...", target.display());
+        std::fs::write(target, serde_json::to_string(&self.subset_index())?)?;
+        Ok(())
+    }
+
+    /// Renders a server configuration file in the given format. For `_headers` files, `prefix`
+    /// is the absolute path the generated files are served from.
+    fn render_server_config(&self, format: ServerConfigFormat, prefix: &str) -> String {
+        const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+        let mut files: Vec<_> = self
+            .webfonts
+            .iter()
+            .flat_map(|x| x.subsets())
+            .flat_map(|x| x.files().map(|(name, _)| name))
+            .collect();
+        files.sort();
+        files.dedup();
+
+        let mut out = String::new();
+        match format {
+            ServerConfigFormat::Headers => {
+                for file in files {
+                    let content_type = if file.ends_with(".woff") {
+                        "font/woff"
+                    } else {
+                        "font/woff2"
+                    };
+                    out.push_str(&format!("{prefix}/{file}\n"));
+                    out.push_str(&format!("  Content-Type: {content_type}\n"));
+                    out.push_str(&format!("  Cache-Control: {CACHE_CONTROL}\n"));
+                    out.push_str("  Access-Control-Allow-Origin: *\n");
+                }
+            }
+            ServerConfigFormat::Htaccess => {
+                out.push_str("<IfModule mod_mime.c>\n");
+                out.push_str("  AddType font/woff2 .woff2\n");
+                out.push_str("  AddType font/woff .woff\n");
+                out.push_str("</IfModule>\n");
+                out.push_str("<IfModule mod_headers.c>\n");
+                for file in files {
+                    // `<Files>` only matches against the file name, not the directory
+                    let file = file.rsplit('/').next().unwrap();
+                    out.push_str(&format!("  <Files \"{file}\">\n"));
+                    out.push_str(&format!("    Header set Cache-Control \"{CACHE_CONTROL}\"\n"));
+                    out.push_str("    Header set Access-Control-Allow-Origin \"*\"\n");
+                    out.push_str("  </Files>\n");
+                }
+                out.push_str("</IfModule>\n");
+            }
+        }
+        out
+    }
+
+    /// Writes a server configuration file into the store that serves the generated files with
+    /// the correct `Content-Type`, long-lived cache headers and CORS headers.
+    ///
+    /// For `_headers` files, the paths are taken from the path component of the first store URI,
+    /// which must be an absolute URL or an absolute path. As `_headers` files only take effect at
+    /// the root of the published site, the file must be merged into the site's own `_headers`
+    /// file unless the store is served from the root.
+    pub fn write_server_config(
+        &self,
+        store_path: impl AsRef<Path>,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
+        format: ServerConfigFormat,
+    ) -> Result<()> {
+        let prefix = match format {
+            ServerConfigFormat::Headers => {
+                let Some(uri) = store_uris.into_iter().next() else {
+                    bail!("`_headers` files require a store URI to find the paths of the fonts.");
+                };
+                let uri = uri.as_ref();
+                let Some(prefix) = absolute_uri_path(uri) else {
+                    bail!(
+                        "`_headers` files require an absolute store URI, as their paths are \
+                         relative to the root of the site: {uri:?}"
+                    );
+                };
+                if !prefix.is_empty() {
+                    warn!(
+                        "The `_headers` file only takes effect at the root of the published \
+                         site. Merge it into the site's `_headers` file if the store is served \
+                         from '{prefix}'."
+                    );
+                }
+                prefix.to_string()
+            }
+            ServerConfigFormat::Htaccess => String::new(),
+        };
+
+        let target = store_path.as_ref().join(format.file_name());
+        info!("Writing server configuration to '{}'...", target.display());
+        std::fs::write(target, self.render_server_config(format, &prefix))?;
+        Ok(())
+    }
+
+    /// Writes a self-contained directory that can be deployed as-is.
+    ///
+    /// The generated files are written to a `fonts` subdirectory along with the subset index and
+    /// an `.htaccess` file, and the CSS, manifest and `_headers` file are written to `fonts.css`,
+    /// `manifest.json` and `_headers` in the package root. If no store URIs are given, the CSS
+    /// refers to the `fonts` directory relative to itself.
+    ///
+    /// The paths in the `_headers` file are taken from the first store URI if it is absolute.
+    /// Otherwise, they are relative to the root of the site, and the package must be deployed
+    /// there for them to match.
+    pub fn write_package(
+        &self,
+        package_path: impl AsRef<Path>,
+        store_uris: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<()> {
+        let package_path = package_path.as_ref();
+        let store_path = package_path.join("fonts");
+        let mut store_uris: Vec<_> = store_uris
+            .into_iter()
+            .map(|x| x.as_ref().to_string())
+            .collect();
+        if store_uris.is_empty() {
+            store_uris.push("fonts".to_string());
+        }
+        let headers_prefix = match absolute_uri_path(&store_uris[0]) {
+            Some(prefix) => prefix.to_string(),
+            None => {
+                let path = store_uris[0].trim_start_matches("./").trim_end_matches('/');
+                format!("/{path}")
+            }
+        };
+
+        info!("Writing package to '{}'...", package_path.display());
+        std::fs::create_dir_all(&store_path)?;
+        self.write_webfonts(&store_path)?;
+        self.write_subset_index(&store_path)?;
+        self.write_manifest(package_path.join("manifest.json"))?;
+        std::fs::write(
+            package_path.join(ServerConfigFormat::Headers.file_name()),
+            self.render_server_config(ServerConfigFormat::Headers, &headers_prefix),
+        )?;
+        self.write_server_config(&store_path, &store_uris, ServerConfigFormat::Htaccess)?;
+        std::fs::write(
+            package_path.join("fonts.css"),
+            self.produce_css(&store_path, &store_uris)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the path component of an absolute URL or path, without a trailing `/`, or `None` if
+/// the URI is relative.
+fn absolute_uri_path(uri: &str) -> Option<&str> {
+    let path = match uri.split_once("//") {
+        Some((scheme, rest)) if scheme.is_empty() || scheme.ends_with(':') => {
+            rest.find('/').map_or("", |idx| &rest[idx..])
+        }
+        _ if uri.starts_with('/') => uri,
+        _ => return None,
+    };
+    Some(path.trim_end_matches('/'))
+}
+
+/// The result of tracing a character through the pipeline with [`WebfontResults::explain`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CharacterExplanation {
+    pub codepoint: String,
+    /// The font stacks in the webroot used to display text containing the character.
+    pub font_stacks: Vec<Vec<String>>,
+    /// The loaded fonts that contain the character.
+    pub covering_fonts: Vec<CharacterFontInfo>,
+    /// Whether the character was assigned to the fallback font, as no font in a font stack using
+    /// it covers it.
+    pub needs_fallback: bool,
+    /// The generated subsets that contain the character.
+    pub subsets: Vec<CharacterSubsetInfo>,
+}
+
+/// How a font containing a character treated it, as part of a [`CharacterExplanation`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CharacterFontInfo {
+    pub font: String,
+    /// Whether the font was processed at all, rather than excluded by family.
+    pub processed: bool,
+    /// Whether the character was assigned to the font, rather than excluded from it.
+    pub used: bool,
+    /// Whether the character is preloaded in the font.
+    pub preloaded: bool,
+}
+
+/// A generated subset containing a character, as part of a [`CharacterExplanation`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CharacterSubsetInfo {
+    pub family: String,
+    pub style: String,
+    pub subset: String,
+    pub file: String,
+}
+
+/// Text in a webroot containing characters that no font in its font stack covers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TofuReport {
+    pub stack: Vec<String>,
+    pub text: String,
+    /// The characters in the text that are not covered.
+    pub missing: String,
+}
+
+/// A description of the generated webfonts, grouped by font family.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebfontManifest {
+    pub font_faces: BTreeMap<String, Vec<ManifestFontFace>>,
+    /// The fonts downloaded from the Google Fonts repository, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gfonts: Option<GfontsSummary>,
+}
+
+/// A generated webfont in a [`WebfontManifest`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestFontFace {
+    pub style_name: String,
+    pub style: String,
+    pub weight_range: RangeInclusive<u32>,
+    pub subsets: Vec<ManifestSubset>,
+}
+
+/// A subset of a generated webfont in a [`WebfontManifest`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestSubset {
+    pub name: String,
+    /// The path of the .woff2 file, relative to the store.
+    pub file: String,
+    /// The size of the .woff2 file in bytes.
+    pub size: usize,
+    /// The subresource integrity hash of the .woff2 file.
+    pub integrity: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub woff_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub woff_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub woff_integrity: Option<String>,
+    pub codepoint_count: usize,
+    /// The codepoint ranges covered by the subset, as inclusive `[start, end]` pairs.
+    pub unicode_ranges: Vec<[u32; 2]>,
+}
+
+/// An entry in the subset index, describing one generated subset file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubsetIndexEntry {
+    pub family: String,
+    pub style: String,
+    pub weight: [u32; 2],
+    pub file: String,
+    /// The codepoint ranges covered by the subset, as inclusive `[start, end]` pairs.
+    pub ranges: Vec<[u32; 2]>,
+}
+
+/// The format of a server configuration file written to the store.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ServerConfigFormat {
+    /// A `_headers` file, as used by Netlify and Cloudflare Pages.
+    Headers,
+    /// An Apache `.htaccess` file.
+    Htaccess,
+}
+impl ServerConfigFormat {
+    /// Returns the name of the configuration file.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ServerConfigFormat::Headers => "_headers",
+            ServerConfigFormat::Htaccess => ".htaccess",
+        }
+    }
 }
 
 pub async fn process_webfont(
@@ -439,7 +1321,15 @@ pub async fn process_webfont(
     let assigned = Arc::new(if plan.flags.contains(FontFlags::DoSubsetting) {
         plan.calculate_subsets(&fonts.font_set, webroot.map(|x| &*x.0))?
     } else {
-        AssignedSubsets::disabled().clone()
+        let mut assigned = AssignedSubsets::disabled().clone();
+        if plan.flags.contains(FontFlags::ExcludeEmoji) {
+            assigned.exclude_emoji();
+        }
+        if plan.flags.contains(FontFlags::BmpOnly) {
+            assigned.bmp_only();
+        }
+        assigned.drop_categories(&plan.dropped_categories);
+        assigned
     });
 
     let mut joins = JoinSet::new();
@@ -464,16 +1354,106 @@ pub async fn process_webfont(
     {
         let span = info_span!("fallback_font");
         let _enter = span.enter();
+
+        let mut primary_coverage = CharacterSet::new();
+        if plan.flags.contains(FontFlags::DedupeFallback)
+            || plan.flags.contains(FontFlags::ForceFallback)
+        {
+            for font in fonts.font_set.as_list() {
+                if plan.family_config.check_font(font) {
+                    primary_coverage.extend(&assigned.get_used_chars(font));
+                }
+            }
+        }
+
+        let plan = plan.clone();
         let assigned = assigned.clone();
         joins.spawn(
-            async move { splitter::make_fallback_font(&plan, &assigned).await }.in_current_span(),
+            async move { splitter::make_fallback_font(&plan, &assigned, &primary_coverage).await }
+                .in_current_span(),
         );
     }
 
-    let webfonts = joins.join_vec().await?.into_iter().map(Arc::new).collect();
+    let mut webfonts: Vec<_> = joins.join_vec().await?.into_iter().map(Arc::new).collect();
+    let shared = dedup_subset_files(&mut webfonts);
+    if shared > 0 {
+        info!("{shared} subsets are identical to other subsets, and will share their files.");
+    }
     Ok(WebfontResults {
         webfonts,
+        plan,
         fallback_info: assigned.get_fallback_info().clone(),
         webroot: webroot.cloned(),
+        assigned,
+        gfonts_summary: fonts.gfonts_summary.clone(),
     })
 }
+
+/// Recompresses every `.woff2` file in an existing store at a different Brotli quality (0 to
+/// 11), keeping file names unchanged. Returns the number of files recompressed.
+pub async fn recompress_store(store: &Path, quality: usize) -> Result<usize> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![store.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|x| x == "woff2") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+
+    let mut joins = JoinSet::new();
+    for path in paths {
+        joins.spawn(async move {
+            let data = std::fs::read(&path)?;
+            let new_data = match font_info::recompress_woff2(&data, quality) {
+                Ok(data) => data,
+                Err(e) => bail!("Could not recompress {}: {e}", path.display()),
+            };
+            info!("Recompressed {}: {} -> {} bytes", path.display(), data.len(), new_data.len());
+            std::fs::write(&path, new_data)?;
+            Ok(())
+        });
+    }
+    Ok(joins.join().await?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_uri_paths() {
+        assert_eq!(absolute_uri_path("https://example.com/static/fonts/"), Some("/static/fonts"));
+        assert_eq!(absolute_uri_path("https://example.com"), Some(""));
+        assert_eq!(absolute_uri_path("//cdn.example.com/fonts"), Some("/fonts"));
+        assert_eq!(absolute_uri_path("/fonts"), Some("/fonts"));
+        assert_eq!(absolute_uri_path("fonts"), None);
+        assert_eq!(absolute_uri_path("../fonts"), None);
+    }
+
+    #[tokio::test]
+    async fn merged_builder_loads_both_fonts() {
+        let dejavu = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/DejaVuSans.ttf");
+        let noto = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../mkwebfont_hb-subset/tests/fonts/NotoSans.ttf",
+        );
+        let fonts = LoadedFontSetBuilder::new()
+            .load_from_disk([dejavu])
+            .merge(
+                LoadedFontSetBuilder::new()
+                    .load_path(Path::new(noto))
+                    .unwrap(),
+            )
+            .build()
+            .await
+            .unwrap();
+        assert!(!fonts.resolve("DejaVu Sans").unwrap().is_empty());
+        assert!(!fonts.resolve("Noto Sans").unwrap().is_empty());
+    }
+}
@@ -1,8 +1,11 @@
 use anyhow::Result;
 use clap::Parser;
-use mkwebfont::{LoadedFontSetBuilder, SplitterPlan, Webroot};
+use mkwebfont::{
+    ExtractorConfig, ExtractorFlags, FontDisplay, LoadedFontSetBuilder, ServerConfigFormat,
+    SplitterPlan, Webroot,
+};
 use mkwebfont_common::FILTER_SPEC;
-use std::{fs::OpenOptions, io, io::Write as IoWrite, path::PathBuf};
+use std::{collections::BTreeMap, fs::OpenOptions, io, io::Write as IoWrite, path::PathBuf};
 use tokio::runtime::Builder;
 use tracing::{error, info, warn};
 
@@ -13,13 +16,23 @@ struct Args {
     /// The font files to generate webfonts from.
     fonts: Vec<PathBuf>,
 
+    /// Directories to recursively search for font files to generate webfonts from.
+    #[arg(long)]
+    font_dir: Vec<PathBuf>,
+
     /// The location to store generated .woff2 files in.
     #[arg(short = 's', long)]
     store: Option<PathBuf>,
 
     /// The URI at which the .woof2 store can be accessed at.
+    ///
+    /// This may be given multiple times for stores mirrored across several hosts. Each generated
+    /// file is assigned to one of the URIs based on a hash of its name.
+    ///
+    /// If this is not given when writing CSS with `--output` or `--append`, the store is referenced
+    /// by its path relative to the CSS file.
     #[arg(short = 'u', long)]
-    store_uri: Option<String>,
+    store_uri: Vec<String>,
 
     /// The path to write the .css file to, replacing the existing contents.
     #[arg(short = 'o', long)]
@@ -33,6 +46,13 @@ struct Args {
     #[arg(short = 'v', long)]
     verbose: bool,
 
+    /// Suppresses warnings of the given categories while processing the webroot, as a
+    /// comma-separated list.
+    ///
+    /// The categories are `generic-family`, `unparsed`, `unsupported` and `missing-resource`.
+    #[arg(long, value_delimiter = ',')]
+    suppress_warnings: Vec<String>,
+
     /// Include only certain font families.
     ///
     /// This is useful when working with TrueType Font Collections.
@@ -49,10 +69,26 @@ struct Args {
     #[arg(long)]
     splitter: Option<SplitterImpl>,
 
+    /// Loads the subsets used by the splitter from a TOML file (or a JSON file ending in `.json`),
+    /// in place of the builtin Google Fonts subsets.
+    ///
+    /// The file is a list of `[[subset]]` tables, each with a `name`, and its characters given as
+    /// a list of unicode `ranges` (such as `"U+0020-007E"`) and/or a string of `chars`. Every
+    /// subset a font contains characters from is kept, in the order declared. Language subsets and
+    /// the grouping of joining scripts such as Arabic are not applied.
+    #[arg(long)]
+    custom_subsets: Option<PathBuf>,
+
     /// Automatically downloads a font family by name from Google Fonts.
     #[arg(short = 'f', long)]
     gfont: Vec<String>,
 
+    /// Downloads a font file from an HTTP(S) URL. This may be given multiple times.
+    ///
+    /// URLs that cannot be loaded are skipped with a warning.
+    #[arg(long)]
+    font_url: Vec<String>,
+
     /// The webroot to automatically generate webfonts for.
     ///
     /// This automatically generates `--subset-data`, `--gfont` and `--store-uri` arguments based
@@ -60,10 +96,29 @@ struct Args {
     #[arg(short = 'r', long)]
     webroot: Option<PathBuf>,
 
+    /// Treats a generic font family in the webroot's CSS as a concrete font family, in the form
+    /// `<generic>=<family>` (such as `system-ui=Inter`). May be specified multiple times.
+    ///
+    /// By default, generic font families are ignored.
+    #[arg(long)]
+    map_generic_family: Vec<String>,
+
+    /// A file to cache the analysis of the webroot in. If no HTML or CSS file in the webroot
+    /// changed since the cache was written, the webroot is not parsed again.
+    #[arg(long)]
+    webroot_cache: Option<PathBuf>,
+
     /// Rewrites the contents at the webroot to use the webfonts.
     #[arg(short = 'w', long)]
     write_to_webroot: bool,
 
+    /// Assumes that any font used for a pseudo-element's `content` may be used for the `content`
+    /// of any pseudo-element rule in the webroot, even ones that do not match any element.
+    ///
+    /// This is useful for icon fonts whose classes are added dynamically by scripts.
+    #[arg(long)]
+    keep_all_content: bool,
+
     /// Enables subsetting the input fonts before splitting them.
     #[arg(long)]
     subset: bool,
@@ -72,7 +127,7 @@ struct Args {
     /// allowed:
     ///
     /// * `@<file path>` - Parses the file at a given path as a new-line seperated list of subset
-    ///   directives.
+    ///   directives. `@-` reads the directives from standard input instead.
     ///
     /// * `<font list>:<text data>` - Specifies that a given font stack is used with the given text
     ///   data.
@@ -85,6 +140,15 @@ struct Args {
     ///   are to be included among the latin characters (or other split subset of the most common
     ///   characters)
     ///
+    /// * `include:<font list>:<text data>` - Specifies that all characters in the given text data
+    ///   are always included in the given fonts, without any fallback resolution. This is meant
+    ///   for dynamic content that is known to use a specific font.
+    ///
+    /// * `lang:<language tag>:<text data>` - Specifies that the given text data is used in the
+    ///   given language (e.g. `ja` or `zh-Hant`). Each language is given its own subsets, so that
+    ///   pages in one language do not load characters used only in another. Characters shared
+    ///   between languages are placed in the subset of the language declared first.
+    ///
     /// A font list is a comma-delimited list of font names.
     ///
     /// Text data may be `@<file path>` to load data from a given file, `#<unicode ranges>` for a
@@ -93,9 +157,305 @@ struct Args {
     #[arg(long)]
     subset_data: Vec<String>,
 
+    /// Keeps every character in the given UTF-8 text file in all fonts. May be specified multiple
+    /// times, and implies `--subset`.
+    ///
+    /// This is a shorthand for `--subset --subset-data *:@<file path>`.
+    #[arg(long)]
+    text_file: Vec<PathBuf>,
+
+    /// Always includes the given characters in a font family, in the form `<font list>:<text
+    /// data>` (for example, `Roboto:@chars.txt`). May be specified multiple times.
+    ///
+    /// This is a shorthand for `--subset-data include:<font list>:<text data>`.
+    #[arg(long)]
+    always_include: Vec<String>,
+
+    /// Excludes characters that are displayed as emoji by default from every font that is not an
+    /// emoji font. This is useful for sites that use a separate emoji font.
+    #[arg(long)]
+    exclude_emoji: bool,
+
+    /// Excludes every character outside the Basic Multilingual Plane (above U+FFFF) from all
+    /// generated fonts, for environments that cannot handle supplementary-plane characters.
+    #[arg(long)]
+    bmp_only: bool,
+
+    /// The Unicode general categories whose characters are never included in generated fonts, as
+    /// a comma-separated list of abbreviations such as `Cc` or `Cf`.
+    ///
+    /// Defaults to `Cc`, dropping control characters while keeping format characters such as the
+    /// zero-width joiner. An empty value keeps every character.
+    #[arg(long, value_delimiter = ',')]
+    drop_categories: Option<Vec<String>>,
+
+    /// Omits the `format("woff2")` hint from generated `@font-face` rules, for compatibility with
+    /// old browsers that mishandle it.
+    #[arg(long)]
+    no_format_hint: bool,
+
+    /// Generates a `.woff` file for every subset alongside the `.woff2` file, and lists both in
+    /// the generated CSS, for browsers that do not support WOFF 2.0.
+    #[arg(long)]
+    emit_woff1: bool,
+
+    /// Minifies the generated font CSS. This is the default.
+    #[arg(long, overrides_with = "no_minify_css")]
+    minify_css: bool,
+
+    /// Writes the generated font CSS in a readable, indented form rather than minifying it.
+    ///
+    /// This does not affect CSS files rewritten in a webroot, which are never minified.
+    #[arg(long, overrides_with = "minify_css")]
+    no_minify_css: bool,
+
+    /// Embeds subsets smaller than the given number of bytes directly in the generated CSS as
+    /// base64 `data:` URIs, rather than referencing them from the store.
+    #[arg(long)]
+    inline_below: Option<usize>,
+
+    /// The fraction of the `latin` and `latin-ext` subsets a font must cover for them to be
+    /// split out before any other subset. Defaults to `0.25`.
+    ///
+    /// Lower values suit sites that are mostly in Latin script, as these subsets are then fetched
+    /// in full on first load. Higher values suit sites in other scripts (such as CJK), keeping
+    /// Latin characters out of the first fetch.
+    #[arg(long)]
+    high_priority_ratio: Option<f64>,
+
+    /// Fonts covering fewer than this many codepoints are emitted as a single subset instead of
+    /// being split, as the overhead of the extra requests outweighs the savings for small fonts.
+    ///
+    /// Defaults to 200. Set this to 0 to split every font.
+    #[arg(long)]
+    min_glyphs_to_split: Option<usize>,
+
+    /// A suffix appended to the family name of every generated font, such as ` (subset)`.
+    ///
+    /// References to these families are renamed to match when using `--write-to-webroot`.
+    #[arg(long)]
+    css_family_suffix: Option<String>,
+
+    /// Wraps the `@font-face` rules of a font family in an `@media` rule, in the form
+    /// `<family>=<media query>` (such as `Roboto=(min-width: 800px)`). This may be given
+    /// multiple times.
+    #[arg(long)]
+    media_query: Vec<String>,
+
+    /// The `font-display` descriptor of the generated `@font-face` rules, which controls how text
+    /// is rendered while the fonts load. Defaults to `swap`.
+    #[arg(long)]
+    font_display: Option<FontDisplayImpl>,
+
+    /// Assigns every character used anywhere in the webroot to the given font stack (such as
+    /// `Roboto`), rather than resolving the font stack of each element from the CSS.
+    ///
+    /// The fonts in the stack must be loaded, for example with `--gfont`.
+    #[arg(long)]
+    single_stack: Option<String>,
+
+    /// How generated files are organized in the store.
+    #[arg(long)]
+    store_layout: Option<StoreLayoutImpl>,
+
+    /// Ignores whitespace and zero-width characters when deciding whether a subset is large
+    /// enough to be split out, so the decision reflects visible glyphs only.
+    #[arg(long)]
+    count_visible_only: bool,
+
+    /// The template used for the file names of the generated subsets, such as
+    /// `{family}-{weight}-{subset}-{hash}.woff2`.
+    ///
+    /// The following placeholders are supported: `{family}`, `{style}`, `{weight}`, `{version}`,
+    /// `{subset}` and `{hash}`. Both `{subset}` and `{hash}` must be present.
+    #[arg(long)]
+    file_name_template: Option<String>,
+
+    /// Derives the hash in the file name of each subset from that subset's contents only, so
+    /// unchanged subsets keep the same file names across builds.
+    #[arg(long)]
+    per_subset_hashing: bool,
+
+    /// Only keeps the OpenType layout rules for the given scripts (such as `latn` or `dev2`) in
+    /// the generated fonts. May be specified multiple times.
+    ///
+    /// By default, the layout rules for every script in the font are kept.
+    #[arg(long)]
+    layout_script: Vec<String>,
+
+    /// Removes the `GPOS` mark positioning features from subsets that contain no combining marks.
+    ///
+    /// This reduces the size of subsets such as Latin ones, while subsets containing combining
+    /// marks still position them correctly.
+    #[arg(long)]
+    prune_mark_positioning: bool,
+
+    /// Keeps the outline of the `.notdef` glyph in the generated fonts.
+    ///
+    /// By default the outline is removed, so characters missing from a font render as blank space
+    /// instead of the font's missing glyph box.
+    #[arg(long)]
+    retain_notdef_outline: bool,
+
+    /// Removes the hinting from the generated fonts to make them smaller, at the cost of worse
+    /// rendering at small sizes on Windows.
+    ///
+    /// This removes hinting instructions and the `cvt `, `fpgm`, `prep`, `hdmx` and `VDMX` tables.
+    #[arg(long)]
+    strip_hinting: bool,
+
+    /// Emits `ascent-override`, `descent-override` and `line-gap-override` on every `@font-face`.
+    ///
+    /// The values are taken from the original font, so all subsets of a font have identical
+    /// vertical metrics.
+    #[arg(long)]
+    metric_overrides: bool,
+
+    /// Keeps every non-hidden variation axis of variable fonts variable, rather than only the
+    /// weight axis. The `@font-face` rules declare the full width and slant ranges of the font.
+    #[arg(long)]
+    preserve_all_axes: bool,
+
+    /// The variation axes of variable fonts kept variable, as a comma-separated list of tags
+    /// (such as `wght,opsz`). Every other axis is pinned to its default value. Defaults to
+    /// `wght`.
+    #[arg(long, value_delimiter = ',')]
+    keep_axes: Option<Vec<String>>,
+
+    /// Fails if any character used is covered by neither the fonts being processed nor the fallback
+    /// fonts, listing the uncovered codepoints.
+    #[arg(long)]
+    require_full_coverage: bool,
+
+    /// Writes a server configuration file into the store that serves the generated files with the
+    /// correct `Content-Type`, cache headers and CORS headers.
+    ///
+    /// `_headers` files require an absolute `--store-uri`, and only take effect at the root of the
+    /// published site.
+    #[arg(long)]
+    emit_server_config: Option<ServerConfigImpl>,
+
+    /// Writes `subset-index.json` into the store, mapping each generated file to the codepoint
+    /// ranges it covers. This is meant for loading subsets on demand from JavaScript.
+    #[arg(long)]
+    emit_subset_index: bool,
+
+    /// Writes a JSON manifest describing every generated font and subset to the given file,
+    /// including the file names, sizes and codepoint ranges of the subsets.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Adds `<link rel="preload">` tags for the base subset of each font family to the `<head>` of
+    /// pages using the fonts when using `--write-to-webroot`.
+    #[arg(long)]
+    preload_links: bool,
+
+    /// Writes `<link rel="preload">` tags for the base subset of each font family to the given
+    /// file, one per line, for inclusion in the `<head>` of pages using the generated CSS.
+    #[arg(long)]
+    preload_output: Option<PathBuf>,
+
+    /// Adds `integrity` attributes with the SHA-384 hash of each file to generated preload links.
+    ///
+    /// As `@font-face` rules cannot carry integrity hashes, browsers may not reuse the preloaded
+    /// files and fetch them twice.
+    #[arg(long)]
+    preload_integrity: bool,
+
+    /// Writes a directory ready to be deployed as-is, containing the generated files, the subset
+    /// index, server configuration files and the CSS as `fonts.css`.
+    ///
+    /// `--store` is not required when this is given. Without `--store-uri`, the CSS refers to the
+    /// generated files relative to its own location.
+    #[arg(long)]
+    package_output: Option<PathBuf>,
+
+    /// Runs the full pipeline, but only reports the files that would be written to the store and
+    /// the files in the webroot that would be modified, without writing anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Splits icon fonts like any other font, rather than keeping fonts that mostly cover private
+    /// use codepoints in a single subset.
+    #[arg(long)]
+    split_icon_fonts: bool,
+
+    /// Removes characters from the fallback font that any of the fonts being processed cover, even
+    /// if they are used in a font stack without that font.
+    #[arg(long)]
+    dedupe_fallback: bool,
+
+    /// Always generates a fallback font covering every character the fonts being processed do
+    /// not, even without a webroot. This downloads a large number of fallback fonts.
+    #[arg(long)]
+    force_fallback: bool,
+
+    /// Fails on fonts whose embedding permissions (`fsType`) prohibit embedding or subsetting
+    /// them, rather than only warning about them.
+    #[arg(long)]
+    respect_fstype: bool,
+
+    /// Fails if any text in the webroot contains characters that no font in its font stack
+    /// (including the fallback font) covers, listing the text affected.
+    #[arg(long)]
+    fail_on_tofu: bool,
+
+    /// Fails if the fonts used for the fallback font cannot be downloaded, rather than continuing
+    /// without a fallback font.
+    #[arg(long)]
+    strict: bool,
+
+    /// Only extracts the characters used by each font stack in the webroot, and prints them as
+    /// JSON data in the same format as `unicode-range` in CSS.
+    ///
+    /// No fonts are loaded or subset in this mode.
+    #[arg(long)]
+    extract_only: bool,
+
+    /// The maximum number of files downloaded from Google Fonts at once.
+    #[arg(long, default_value_t = 8)]
+    download_concurrency: usize,
+
+    /// The maximum number of subsets encoded at once. Defaults to the number of CPUs.
+    ///
+    /// Lowering this reduces peak memory use when subsetting large fonts.
+    #[arg(long)]
+    encode_concurrency: Option<usize>,
+
+    /// Fails if more than the given number of font files would be downloaded from Google Fonts to
+    /// cover the fonts used in the webroot.
+    #[arg(long)]
+    max_fonts: Option<usize>,
+
+    /// Fails if the bundled Google Fonts repository revision does not match the given commit hash.
+    ///
+    /// An abbreviated hash may be given. This can be used in CI to detect unexpected changes to
+    /// the bundled data after upgrading mkwebfont.
+    #[arg(long)]
+    assert_gfonts_rev: Option<String>,
+
+    /// Traces a single character (given as the character itself, or as `U+XXXX`) through the
+    /// pipeline, printing which fonts cover it, which font stacks use it and which subsets it was
+    /// assigned to as JSON, rather than writing any files.
+    #[arg(long)]
+    explain: Option<String>,
+
     /// Dumps all loaded fonts into a directory and return JSON data representing the paths.
     #[arg(long)]
     dump_fonts: Option<PathBuf>,
+
+    /// Includes the full codepoint coverage of each font in the output of `--dump-fonts`.
+    ///
+    /// The coverage is given as a sorted, delta-encoded list of codepoints.
+    #[arg(long)]
+    dump_codepoints: bool,
+
+    /// Recompresses every .woff2 file already in the store at the given Brotli quality (0 to 11)
+    /// and exits, without generating any new files.
+    ///
+    /// This can be used to trade build time for file size after the fact.
+    #[arg(long)]
+    recompress: Option<usize>,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -105,21 +465,115 @@ enum SplitterImpl {
     Gfonts,
 }
 
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum FontDisplayImpl {
+    Auto,
+    Block,
+    Swap,
+    Fallback,
+    Optional,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum StoreLayoutImpl {
+    /// All files are written directly into the store.
+    Flat,
+    /// The files for each font family are written into a subdirectory named after it.
+    Nested,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum ServerConfigImpl {
+    /// A `_headers` file, as used by Netlify and Cloudflare Pages.
+    Headers,
+    /// An Apache `.htaccess` file.
+    Htaccess,
+}
+
 async fn main_impl(args: Args) -> Result<()> {
     // check arguments
     if args.append.is_some() && args.output.is_some() {
         error!("Only one of `--append` and `--output` may be used in one invocation.");
         std::process::exit(1)
     }
-    if args.store.is_none() && args.dump_fonts.is_none() {
+    if args.extract_only && args.webroot.is_none() {
+        error!("`--extract-only` requires the `--webroot <WEBROOT>` parameter.");
+        std::process::exit(1)
+    }
+    if args.store.is_none()
+        && args.dump_fonts.is_none()
+        && args.explain.is_none()
+        && args.package_output.is_none()
+        && !args.extract_only
+    {
         error!("`--store <STORE>` parameter must be provided.");
         std::process::exit(1)
     }
+    if args.store.is_none() && args.package_output.is_some() {
+        let store_outputs = [
+            ("--output", args.output.is_some()),
+            ("--append", args.append.is_some()),
+            ("--manifest", args.manifest.is_some()),
+            ("--preload-output", args.preload_output.is_some()),
+            ("--emit-server-config", args.emit_server_config.is_some()),
+            ("--emit-subset-index", args.emit_subset_index),
+            ("--write-to-webroot", args.write_to_webroot),
+        ];
+        if let Some((name, _)) = store_outputs.into_iter().find(|x| x.1) {
+            error!("`{name}` requires the `--store <STORE>` parameter.");
+            std::process::exit(1)
+        }
+    }
+    if args.preload_output.is_some() && args.store_uri.is_empty() {
+        error!("`--preload-output` requires the `--store-uri <STORE_URI>` parameter.");
+        std::process::exit(1)
+    }
+    if matches!(args.emit_server_config, Some(ServerConfigImpl::Headers))
+        && !args
+            .store_uri
+            .first()
+            .is_some_and(|x| x.starts_with('/') || x.contains("://"))
+    {
+        error!("`--emit-server-config headers` requires an absolute `--store-uri <STORE_URI>`.");
+        std::process::exit(1)
+    }
+    if args.write_to_webroot && args.webroot.is_none() {
+        warn!("`--write-to-webroot` specified with no webroot. Ignoring.");
+    }
+    mkwebfont::set_download_concurrency(args.download_concurrency);
+    if let Some(limit) = args.encode_concurrency {
+        mkwebfont::set_encode_concurrency(limit);
+    }
+    if let Some(rev) = &args.assert_gfonts_rev {
+        let bundled = mkwebfont::gfonts_revision();
+        let rev = rev.trim().to_lowercase();
+        if rev.is_empty() || !bundled.to_lowercase().starts_with(&rev) {
+            error!(
+                "Bundled Google Fonts revision {bundled} does not match expected revision {rev}."
+            );
+            std::process::exit(1)
+        }
+    }
+    if let Some(quality) = args.recompress {
+        let Some(store) = &args.store else {
+            error!("`--recompress` requires the `--store <STORE>` parameter.");
+            std::process::exit(1)
+        };
+        info!("Recompressing store at quality {quality}...");
+        let count = mkwebfont::recompress_store(store, quality).await?;
+        info!("Recompressed {count} files.");
+        return Ok(());
+    }
     if !args.exclude.is_empty() && !args.include.is_empty() {
         warn!("Only one of `--family` and `--exclude` may be used in one invocation.");
         std::process::exit(1)
     }
-    if args.fonts.is_empty() && args.gfont.is_empty() && args.webroot.is_none() {
+    if args.fonts.is_empty()
+        && args.font_dir.is_empty()
+        && args.gfont.is_empty()
+        && args.font_url.is_empty()
+        && args.webroot.is_none()
+    {
         warn!("No fonts sources were specified! An empty .css file will be generated.");
     }
 
@@ -142,37 +596,278 @@ async fn main_impl(args: Args) -> Result<()> {
             ctx.gfonts_splitter();
         }
     }
+    if let Some(path) = &args.custom_subsets {
+        ctx.custom_subsets(path)?;
+    }
     if args.subset {
         ctx.subset();
     }
+    if args.metric_overrides {
+        ctx.metric_overrides();
+    }
+    if args.preserve_all_axes {
+        ctx.preserve_all_axes();
+    }
+    if let Some(axes) = &args.keep_axes {
+        let mut list = Vec::new();
+        for axis in axes.iter().filter(|x| !x.is_empty()) {
+            list.push(mkwebfont::parse_axis_name(axis)?);
+        }
+        ctx.keep_axes(&list);
+    }
+    if args.require_full_coverage {
+        ctx.require_full_coverage();
+    }
+    if args.exclude_emoji {
+        ctx.exclude_emoji();
+    }
+    if args.bmp_only {
+        ctx.bmp_only();
+    }
+    if args.strict {
+        ctx.strict();
+    }
+    if args.no_format_hint {
+        ctx.no_format_hint();
+    }
+    if args.emit_woff1 {
+        ctx.emit_woff1();
+    }
+    if args.preload_links {
+        ctx.preload_links();
+    }
+    if args.preload_integrity {
+        ctx.preload_integrity();
+    }
+    if args.no_minify_css {
+        ctx.minify_css(false);
+    }
+    if let Some(bytes) = args.inline_below {
+        ctx.inline_below(bytes);
+    }
+    if let Some(ratio) = args.high_priority_ratio {
+        ctx.high_priority_ratio(ratio)?;
+    }
+    if let Some(count) = args.min_glyphs_to_split {
+        ctx.min_glyphs_to_split(count);
+    }
+    if let Some(suffix) = &args.css_family_suffix {
+        ctx.family_suffix(suffix);
+    }
+    for mapping in &args.media_query {
+        let Some((family, query)) = mapping.split_once('=') else {
+            error!("`--media-query` must be in the form `<family>=<media query>`: {mapping}");
+            std::process::exit(1)
+        };
+        ctx.family_media_query(family.trim(), query.trim())?;
+    }
+    if let Some(display) = &args.font_display {
+        ctx.font_display(match display {
+            FontDisplayImpl::Auto => FontDisplay::Auto,
+            FontDisplayImpl::Block => FontDisplay::Block,
+            FontDisplayImpl::Swap => FontDisplay::Swap,
+            FontDisplayImpl::Fallback => FontDisplay::Fallback,
+            FontDisplayImpl::Optional => FontDisplay::Optional,
+        });
+    }
+    if let Some(stack) = &args.single_stack {
+        ctx.single_stack(stack);
+    }
+    if let Some(StoreLayoutImpl::Nested) = args.store_layout {
+        ctx.nested_store();
+    }
+    if args.count_visible_only {
+        ctx.count_visible_only();
+    }
+    if args.prune_mark_positioning {
+        ctx.prune_mark_positioning();
+    }
+    if args.split_icon_fonts {
+        ctx.split_icon_fonts();
+    }
+    if args.dedupe_fallback {
+        ctx.dedupe_fallback();
+    }
+    if args.force_fallback {
+        ctx.force_fallback();
+    }
+    if args.respect_fstype {
+        ctx.respect_fs_type();
+    }
+    if args.retain_notdef_outline {
+        ctx.retain_notdef_outline();
+    }
+    if args.strip_hinting {
+        ctx.strip_hinting();
+    }
+    if args.dry_run {
+        ctx.dry_run();
+    }
+    if args.per_subset_hashing {
+        ctx.per_subset_hashing();
+    }
+    if let Some(template) = &args.file_name_template {
+        ctx.file_name_template(template)?;
+    }
+    if let Some(categories) = &args.drop_categories {
+        ctx.drop_categories(categories.iter().filter(|x| !x.is_empty()))?;
+    }
+    if !args.layout_script.is_empty() {
+        ctx.layout_scripts(&args.layout_script)?;
+    }
+    for path in &args.text_file {
+        ctx.text_file(path)?;
+    }
     for spec in args.subset_data {
         ctx.subset_spec(&spec);
     }
+    for spec in args.always_include {
+        ctx.subset_spec(&format!("include:{spec}"));
+    }
 
     // load webroot
+    let mut extractor_config = ExtractorConfig::default();
+    for mapping in &args.map_generic_family {
+        let Some((generic, family)) = mapping.split_once('=') else {
+            error!("`--map-generic-family` must be in the form `<generic>=<family>`: {mapping}");
+            std::process::exit(1)
+        };
+        extractor_config.generic_family(generic.trim(), family.trim());
+    }
+    if args.keep_all_content {
+        extractor_config
+            .flags
+            .insert(ExtractorFlags::KeepAllContent);
+    }
     let webroot = match args.webroot {
-        Some(root) => Some(Webroot::load(&root).await?),
+        Some(root) => {
+            let config = &extractor_config;
+            match &args.webroot_cache {
+                Some(cache) => Some(Webroot::load_cached(&root, config, cache).await?),
+                None => Some(Webroot::load_with_config(&root, config).await?),
+            }
+        }
         None => None,
     };
 
+    // extract-only pass
+    if args.extract_only {
+        let usage = webroot.unwrap().font_stack_usage();
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+        return Ok(());
+    }
+
     // load fonts
     let mut fonts = LoadedFontSetBuilder::new();
     fonts = fonts.load_from_disk(&args.fonts);
+    fonts = fonts.load_from_dir(&args.font_dir);
     fonts = fonts.load_from_gfonts(&args.gfont);
+    fonts = fonts.load_from_urls(&args.font_url);
     if let Some(root) = &webroot {
         fonts = fonts.add_from_webroot(&root);
+        if let Some(limit) = args.max_fonts {
+            fonts = fonts.max_webroot_downloads(limit);
+        }
     }
 
+    let fonts = fonts.build().await?;
+
     // dump fonts pass
     if let Some(path) = args.dump_fonts {
         info!("Dumping fonts to disk...");
-        let result = fonts.build().await?.dump_fonts(&path, &ctx.build())?;
+        let result = fonts.dump_fonts(&path, &ctx.build(), args.dump_codepoints)?;
         println!("{}", serde_json::to_string_pretty(&result)?);
         return Ok(());
     }
 
     // process webfonts
-    let styles = mkwebfont::process_webfont(&ctx, &fonts.build().await?, webroot.as_ref()).await?;
+    let styles = mkwebfont::process_webfont(&ctx, &fonts, webroot.as_ref()).await?;
+
+    // explain pass
+    if let Some(explain) = &args.explain {
+        let ch = match explain
+            .strip_prefix("U+")
+            .or_else(|| explain.strip_prefix("u+"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+            None if explain.chars().count() == 1 => explain.chars().next(),
+            None => None,
+        };
+        let Some(ch) = ch else {
+            error!("`--explain` must be given a single character or a codepoint: {explain}");
+            std::process::exit(1)
+        };
+        let explanation = styles.explain(&fonts, ch);
+        println!("{}", serde_json::to_string_pretty(&explanation)?);
+        return Ok(());
+    }
+
+    if args.fail_on_tofu {
+        let tofu = styles.find_tofu();
+        if !tofu.is_empty() {
+            for report in &tofu {
+                error!(
+                    "Characters {:?} are not covered by [{}] in text: {:?}",
+                    report.missing,
+                    report.stack.join(", "),
+                    report.text,
+                );
+            }
+            error!("{} pieces of text contain characters that are not covered.", tofu.len());
+            std::process::exit(1)
+        }
+    }
+
+    // dry run pass
+    if args.dry_run {
+        if let Some(path) = &args.package_output {
+            info!("Would write package to '{}'.", path.display());
+        }
+        if let Some(store) = &args.store {
+            let mut files = BTreeMap::new();
+            for font in &styles.webfonts {
+                for subset in font.subsets() {
+                    files.extend(subset.files().map(|(name, data)| (name, data.len())));
+                }
+            }
+            let total: usize = files.values().sum();
+            info!("Would write {} files ({total} bytes) to '{}':", files.len(), store.display());
+            for (name, size) in &files {
+                info!("  - {name} ({size} bytes)");
+            }
+
+            if args.emit_server_config.is_some() {
+                info!("Would write server configuration to '{}'.", store.display());
+            }
+            if args.emit_subset_index {
+                info!("Would write subset index to '{}'.", store.display());
+            }
+        }
+        let outputs = [&args.manifest, &args.preload_output, &args.output, &args.append];
+        for path in outputs.into_iter().flatten() {
+            info!("Would write '{}'.", path.display());
+        }
+        if let Some(store) = &args.store {
+            if args.write_to_webroot && webroot.is_some() {
+                if store.exists() || !args.store_uri.is_empty() {
+                    styles.rewrite_webroot(store, &args.store_uri).await?;
+                } else {
+                    warn!("The store does not exist yet, so webroot changes cannot be reported.");
+                }
+            }
+        }
+        info!("Done!");
+        return Ok(());
+    }
+
+    // write package pass
+    if let Some(path) = &args.package_output {
+        styles.write_package(path, &args.store_uri)?;
+        if args.store.is_none() {
+            info!("Done!");
+            return Ok(());
+        }
+    }
 
     // write webfonts to store and render css
     let count: usize = styles.webfonts.iter().map(|x| x.subset_count()).sum();
@@ -185,46 +880,75 @@ async fn main_impl(args: Args) -> Result<()> {
     styles.write_webfonts(&store)?;
 
     // write webfonts to the webroot.
-    let store_uri = if let Some(store_uri) = args.store_uri {
-        Some(store_uri)
-    } else {
-        None
-    };
-    if args.write_to_webroot {
-        if webroot.is_some() {
-            styles.rewrite_webroot(&store, store_uri.as_ref()).await?;
-        } else {
-            warn!("`--write-to-webroot` specified with no webroot. Ignoring.");
-        }
+    let store_uri = &args.store_uri;
+    if let Some(format) = args.emit_server_config {
+        let format = match format {
+            ServerConfigImpl::Headers => ServerConfigFormat::Headers,
+            ServerConfigImpl::Htaccess => ServerConfigFormat::Htaccess,
+        };
+        styles.write_server_config(&store, store_uri, format)?;
+    }
+    if args.emit_subset_index {
+        styles.write_subset_index(&store)?;
+    }
+    if let Some(target) = &args.manifest {
+        styles.write_manifest(target)?;
+    }
+    if let Some(target) = &args.preload_output {
+        // `--store-uri` is checked to be present with the other arguments
+        info!("Writing preload links to '{}'...", target.display());
+        let mut links = styles.produce_preload_links(&store_uri[0])?.join("\n");
+        links.push('\n');
+        std::fs::write(target, links)?;
+    }
+    if args.write_to_webroot && webroot.is_some() {
+        styles.rewrite_webroot(&store, store_uri).await?;
     }
 
     // write css to output
     if let Some(target) = args.output {
         info!("Writing CSS to '{}'...", target.display());
-        let css = styles.produce_css(&store, store_uri.as_ref())?;
+        let css = styles.produce_css_at(&target, &store, store_uri)?;
         std::fs::write(target, css)?;
     } else if let Some(target) = args.append {
         info!("Appending CSS to '{}'...", target.display());
-        let mut file = OpenOptions::new().write(true).append(true).open(target)?;
-        let css = styles.produce_css(&store, store_uri.as_ref())?;
+        let css = styles.produce_css_at(&target, &store, store_uri)?;
+        let mut file = OpenOptions::new().write(true).append(true).open(&target)?;
         file.write_all(css.as_bytes())?
     } else if !webroot.is_some() || !args.write_to_webroot {
-        let css = styles.produce_css(&store, store_uri.as_ref())?;
+        let css = styles.produce_css(&store, store_uri)?;
         println!("{}", css);
     }
 
     // finalize
+    if let Some(summary) = fonts.gfonts_summary() {
+        info!(
+            "Fonts downloaded from the Google Fonts repository (r{}, {}):",
+            summary
+                .repo_revision
+                .get(..7)
+                .unwrap_or(&summary.repo_revision),
+            summary.repo_date,
+        );
+        for font in &summary.fonts {
+            info!("  - {} / {} / {}", font.family, font.style, font.version);
+        }
+    }
     info!("Done!");
     Ok(())
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    let mut filter = if args.verbose { FILTER_SPEC } else { "info" }.to_string();
+    for category in &args.suppress_warnings {
+        filter.push_str(&format!(",{}=error", mkwebfont::warnings::warning_target(category)?));
+    }
     tracing_subscriber::fmt()
-        .with_env_filter(if args.verbose { FILTER_SPEC } else { "info" })
+        .with_env_filter(filter)
         .with_writer(io::stderr)
         .init();
 
-    let rt = Builder::new_multi_thread().build()?;
+    let rt = Builder::new_multi_thread().enable_all().build()?;
     rt.block_on(main_impl(args))
 }
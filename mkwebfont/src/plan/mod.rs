@@ -1,20 +1,44 @@
-use anyhow::Result;
+use anyhow::{bail, ensure, Result};
 use enumset::*;
-use mkwebfont_extract_web::WebrootInfo;
-use mkwebfont_fontops::font_info::{FontFaceSet, FontFaceWrapper};
-use std::{collections::HashSet, ops::Deref, sync::Arc};
+use mkwebfont_common::character_set::CharacterSet;
+use mkwebfont_extract_web::{FontDisplay, WebrootInfo};
+use mkwebfont_fontops::{
+    font_info::{parse_script_tag, AxisName, FontFaceSet, FontFaceWrapper},
+    gfonts::gfonts_subsets::WebfontData,
+    subsetter::validate_file_name_template,
+};
+use std::{collections::HashSet, ops::Deref, path::Path, sync::Arc};
+use unicode_properties::GeneralCategory;
 
 mod subsetter;
 
 pub use subsetter::{AssignedSubsets, SubsetDataBuilder};
 
+/// Fonts covering fewer codepoints than this are emitted as a single subset by default, as they are
+/// usually already subset.
+const DEFAULT_MIN_GLYPHS_TO_SPLIT: usize = 200;
+
 /// A loaded configuration for font splitting.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct LoadedSplitterPlan(pub(crate) Arc<SplitterPlanData>);
+#[derive(Debug)]
 pub struct SplitterPlanData {
     pub family_config: FontFamilyConfig,
     pub flags: EnumSet<FontFlags>,
     pub subset_specs: Vec<String>,
+    pub target_text: CharacterSet,
+    pub file_name_template: Option<Arc<str>>,
+    pub layout_scripts: Option<Arc<[[u8; 4]]>>,
+    pub kept_axes: Option<Arc<[AxisName]>>,
+    pub inline_below: Option<usize>,
+    pub high_priority_ratio: Option<f64>,
+    pub min_glyphs_to_split: usize,
+    pub family_suffix: Option<Arc<str>>,
+    pub family_media: Vec<(String, String)>,
+    pub font_display: FontDisplay,
+    pub single_stack: Option<Arc<str>>,
+    pub dropped_categories: Arc<[GeneralCategory]>,
+    pub custom_subsets: Option<Arc<WebfontData>>,
 }
 impl Deref for LoadedSplitterPlan {
     type Target = SplitterPlanData;
@@ -30,11 +54,24 @@ impl SplitterPlanData {
         webroot: Option<&WebrootInfo>,
     ) -> Result<AssignedSubsets> {
         let mut builder = SubsetDataBuilder::default();
+        if self.flags.contains(FontFlags::ExcludeEmoji) {
+            builder.exclude_emoji();
+        }
+        if self.flags.contains(FontFlags::BmpOnly) {
+            builder.bmp_only();
+        }
+        builder.drop_categories(&self.dropped_categories);
+        builder.push_all_text(&self.target_text);
         for spec in &self.subset_specs {
             builder.push_spec(fonts, &spec)?;
         }
         if let Some(webroot) = webroot {
-            builder.push_webroot_info(fonts, webroot)?;
+            builder.push_font_face_ranges(fonts, webroot);
+            if let Some(stack) = &self.single_stack {
+                builder.push_webroot_single_stack(fonts, webroot, stack)?;
+            } else {
+                builder.push_webroot_info(fonts, webroot)?;
+            }
         }
         Ok(builder.build())
     }
@@ -61,6 +98,28 @@ pub enum FontFlags {
     NoSplitter,
     GfontsSplitter,
     DoSubsetting,
+    MetricOverrides,
+    PreserveAllAxes,
+    RequireFullCoverage,
+    ExcludeEmoji,
+    Strict,
+    NoFormatHint,
+    NoMinifyCss,
+    BmpOnly,
+    NestedStore,
+    CountVisibleOnly,
+    PruneMarkPositioning,
+    RetainNotdefOutline,
+    DedupeFallback,
+    SplitIconFonts,
+    ForceFallback,
+    RespectFsType,
+    StripHinting,
+    EmitWoff1,
+    PreloadLinks,
+    PreloadIntegrity,
+    DryRun,
+    PerSubsetHashing,
 }
 
 /// Represents a configuration for font splitting.
@@ -69,6 +128,19 @@ pub struct SplitterPlan {
     family_config: FontFamilyConfig,
     pub(crate) flags: EnumSet<FontFlags>,
     subset_specs: Vec<String>,
+    target_text: CharacterSet,
+    file_name_template: Option<Arc<str>>,
+    layout_scripts: Option<Vec<[u8; 4]>>,
+    kept_axes: Option<Vec<AxisName>>,
+    inline_below: Option<usize>,
+    high_priority_ratio: Option<f64>,
+    min_glyphs_to_split: usize,
+    family_suffix: Option<Arc<str>>,
+    family_media: Vec<(String, String)>,
+    font_display: FontDisplay,
+    single_stack: Option<Arc<str>>,
+    dropped_categories: Vec<GeneralCategory>,
+    custom_subsets: Option<Arc<WebfontData>>,
 }
 impl SplitterPlan {
     pub fn new() -> SplitterPlan {
@@ -76,6 +148,19 @@ impl SplitterPlan {
             family_config: FontFamilyConfig::AllFonts,
             flags: Default::default(),
             subset_specs: vec![],
+            target_text: CharacterSet::new(),
+            file_name_template: None,
+            layout_scripts: None,
+            kept_axes: None,
+            inline_below: None,
+            high_priority_ratio: None,
+            min_glyphs_to_split: DEFAULT_MIN_GLYPHS_TO_SPLIT,
+            family_suffix: None,
+            family_media: Vec::new(),
+            font_display: FontDisplay::Swap,
+            single_stack: None,
+            dropped_categories: vec![GeneralCategory::Control],
+            custom_subsets: None,
         }
     }
 
@@ -132,17 +217,419 @@ impl SplitterPlan {
         self
     }
 
+    /// Emits `ascent-override`, `descent-override` and `line-gap-override` taken from the original
+    /// font on every generated `@font-face`, so all subsets of a font share the same metrics.
+    pub fn metric_overrides(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::MetricOverrides);
+        self
+    }
+
+    /// Keeps every non-hidden variation axis of variable fonts variable in the generated subsets,
+    /// rather than only the weight axis.
+    pub fn preserve_all_axes(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::PreserveAllAxes);
+        self
+    }
+
+    /// Sets the variation axes of variable fonts that are kept variable in the generated subsets.
+    /// Every other axis is pinned to its default value. By default, only the weight axis is kept.
+    ///
+    /// The ranges of kept weight, width and slant axes are declared in the generated
+    /// `@font-face` rules.
+    pub fn keep_axes(&mut self, axes: &[AxisName]) -> &mut Self {
+        self.kept_axes = Some(axes.to_vec());
+        self
+    }
+
+    /// Fails if any character used is covered by neither the fonts being processed nor the
+    /// fallback fonts.
+    pub fn require_full_coverage(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::RequireFullCoverage);
+        self
+    }
+
+    /// Excludes characters that are displayed as emoji by default from every font that is not an
+    /// emoji font, for sites that use a separate emoji font.
+    pub fn exclude_emoji(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::ExcludeEmoji);
+        self
+    }
+
+    /// Excludes every character outside the Basic Multilingual Plane (above U+FFFF) from all
+    /// generated fonts, for environments that cannot handle supplementary-plane characters.
+    pub fn bmp_only(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::BmpOnly);
+        self
+    }
+
+    /// Fails instead of continuing with reduced output when optional data cannot be downloaded.
+    ///
+    /// Currently, this only affects the fonts used for the fallback font.
+    pub fn strict(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::Strict);
+        self
+    }
+
+    /// Omits the `format("woff2")` hint from the `src` of generated `@font-face` rules, for
+    /// compatibility with old browsers that mishandle it.
+    pub fn no_format_hint(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::NoFormatHint);
+        self
+    }
+
+    /// Generates a .woff file for every subset in addition to the .woff2 file, and lists it as a
+    /// second source in the generated `@font-face` rules, for browsers without WOFF 2.0 support.
+    pub fn emit_woff1(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::EmitWoff1);
+        self
+    }
+
+    /// Adds `<link rel="preload">` tags for the base subset of each font family to the `<head>` of
+    /// HTML files that use the generated fonts, when rewriting a webroot. This lets browsers start
+    /// fetching the most commonly used characters before the CSS is parsed.
+    pub fn preload_links(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::PreloadLinks);
+        self
+    }
+
+    /// Adds `integrity` attributes with the SHA-384 hash of each file to generated preload links.
+    ///
+    /// `@font-face` rules have no way to specify an integrity hash, and some browsers do not reuse
+    /// a preloaded file for a font request without one, so this may cause fonts to be fetched
+    /// twice. The hashes are always available through [`SubsetInfo::integrity`] and the manifest.
+    ///
+    /// [`SubsetInfo::integrity`]: crate::SubsetInfo::integrity
+    pub fn preload_integrity(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::PreloadIntegrity);
+        self
+    }
+
+    /// Makes [`WebfontResults::rewrite_webroot`] only log the files in the webroot it would
+    /// modify, without writing anything.
+    ///
+    /// [`WebfontResults::rewrite_webroot`]: crate::WebfontResults::rewrite_webroot
+    pub fn dry_run(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::DryRun);
+        self
+    }
+
+    /// Sets whether the detached font CSS is minified. This is enabled by default.
+    pub fn minify_css(&mut self, minify: bool) -> &mut Self {
+        if minify {
+            self.flags.remove(FontFlags::NoMinifyCss);
+        } else {
+            self.flags.insert(FontFlags::NoMinifyCss);
+        }
+        self
+    }
+
+    /// Embeds subsets smaller than the given number of bytes directly in the generated CSS as
+    /// `data:` URIs, saving a request for tiny subsets.
+    pub fn inline_below(&mut self, bytes: usize) -> &mut Self {
+        self.inline_below = Some(bytes);
+        self
+    }
+
+    /// Sets the fraction of the high priority subsets (`latin` and `latin-ext`) a font must cover
+    /// for them to be split out before any other subsets. This defaults to `0.25`.
+    ///
+    /// Lower values make these subsets complete more often, which suits mostly Latin sites, while
+    /// higher values keep Latin characters out of the first fetch for sites in other scripts.
+    pub fn high_priority_ratio(&mut self, ratio: f64) -> Result<&mut Self> {
+        ensure!((0.0..=1.0).contains(&ratio), "High priority ratio must be between 0 and 1.");
+        self.high_priority_ratio = Some(ratio);
+        Ok(self)
+    }
+
+    /// Emits fonts covering fewer than the given number of codepoints as a single subset, rather
+    /// than splitting them. This defaults to 200, and setting it to 0 splits every font.
+    ///
+    /// For small fonts, the overhead of an extra request for each subset outweighs the savings from
+    /// only downloading the subsets that are used, and such fonts have usually already been
+    /// subset. The `unicode-range` of the subset is still emitted as usual.
+    pub fn min_glyphs_to_split(&mut self, count: usize) -> &mut Self {
+        self.min_glyphs_to_split = count;
+        self
+    }
+
+    /// Appends a suffix to the family name of every generated font (e.g. `Roboto` becomes
+    /// `Roboto (subset)`), so the output can be used alongside existing fonts without clashing.
+    ///
+    /// References to these families are renamed to match when rewriting a webroot.
+    pub fn family_suffix(&mut self, suffix: &str) -> &mut Self {
+        self.family_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Wraps the `@font-face` rules generated for a font family in an `@media` rule with the given
+    /// media query (such as `(min-width: 800px)`), so the family is only loaded when it matches.
+    pub fn family_media_query(&mut self, family: &str, query: &str) -> Result<&mut Self> {
+        mkwebfont_extract_web::validate_media_query(query)?;
+        self.family_media
+            .push((family.to_string(), query.to_string()));
+        Ok(self)
+    }
+
+    /// Sets the `font-display` descriptor of the generated `@font-face` rules, which controls how
+    /// text is rendered while the fonts load. This defaults to [`FontDisplay::Swap`].
+    pub fn font_display(&mut self, display: FontDisplay) -> &mut Self {
+        self.font_display = display;
+        self
+    }
+
+    /// Assigns every character used anywhere in the webroot to a single font stack (such as
+    /// `Roboto` or `Roboto, Noto Sans`), rather than resolving the font stack used by each element
+    /// through the CSS cascade.
+    ///
+    /// This suits simple sites that use one font throughout, and also covers text that is styled
+    /// dynamically. The fonts in the stack must be loaded.
+    pub fn single_stack(&mut self, stack: &str) -> &mut Self {
+        self.single_stack = Some(stack.into());
+        self
+    }
+
+    /// Places the files for each font family in a subdirectory of the store named after the
+    /// family, rather than writing all files into the store directly.
+    pub fn nested_store(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::NestedStore);
+        self
+    }
+
+    /// Ignores whitespace and zero-width characters (general categories `Zs` and `Cf`) when
+    /// deciding whether a subset is large enough to be split out. These characters are still
+    /// included in the subsets themselves.
+    pub fn count_visible_only(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::CountVisibleOnly);
+        self
+    }
+
+    /// Removes the `GPOS` mark positioning features (`mark`, `mkmk`, `abvm` and `blwm`) from
+    /// subsets that contain no combining marks, such as most Latin subsets. Subsets containing
+    /// combining marks keep them, so diacritics are still positioned correctly.
+    pub fn prune_mark_positioning(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::PruneMarkPositioning);
+        self
+    }
+
+    /// Keeps the outline of the `.notdef` glyph in the generated subsets.
+    ///
+    /// By default, the outline is removed to make subsets smaller, so characters the font
+    /// unexpectedly lacks a glyph for render as blank space rather than as the font's missing
+    /// glyph box. This only matters when a subset is used for characters it does not contain,
+    /// which the generated `unicode-range`s normally prevent.
+    pub fn retain_notdef_outline(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::RetainNotdefOutline);
+        self
+    }
+
+    /// Removes the hinting from the generated subsets to make them smaller, at the cost of worse
+    /// rendering at small sizes on platforms that use it (mainly Windows).
+    ///
+    /// This strips the TrueType instructions from `glyf`, drops the `cvt `, `fpgm`, `prep`, `hdmx`
+    /// and `VDMX` tables, and removes the hints from `CFF` fonts. The `gasp` table is kept either
+    /// way. By default, hinting is kept.
+    pub fn strip_hinting(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::StripHinting);
+        self
+    }
+
+    /// Removes characters from the fallback font that are covered by any of the fonts being
+    /// processed, even if those fonts are not part of the font stack the characters are used in.
+    ///
+    /// This minimizes the size of the fallback font, at the cost of such characters being rendered
+    /// with a system font in the font stacks that do not include a font covering them.
+    pub fn dedupe_fallback(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::DedupeFallback);
+        self
+    }
+
+    /// Always generates a fallback font, covering every codepoint in the bundled fallback fonts
+    /// that none of the fonts being processed cover.
+    ///
+    /// Without this, a fallback font is only generated for characters found in a webroot or in
+    /// subset data that the fonts in their font stacks do not cover.
+    pub fn force_fallback(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::ForceFallback);
+        self
+    }
+
+    /// Refuses to process fonts whose embedding permissions (`fsType`) prohibit embedding or
+    /// subsetting them, rather than only warning about them.
+    pub fn respect_fs_type(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::RespectFsType);
+        self
+    }
+
+    /// Splits icon fonts like any other font. By default, fonts where most codepoints are in the
+    /// private use areas are assumed to be icon fonts, and are kept as a single subset.
+    ///
+    /// Only the codepoints the font covers are used to detect icon fonts. Whether its characters
+    /// are used only through the CSS `content` property is not taken into account.
+    pub fn split_icon_fonts(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::SplitIconFonts);
+        self
+    }
+
+    /// Derives the hash in the file name of each subset from the contents of that subset only.
+    ///
+    /// By default, the hash covers every subset of a font, so any change to a font renames all of
+    /// its files. With this, subsets whose contents did not change keep their file names across
+    /// builds, and stay in browser caches.
+    pub fn per_subset_hashing(&mut self) -> &mut Self {
+        self.flags.insert(FontFlags::PerSubsetHashing);
+        self
+    }
+
+    /// Sets the template used for the file names of the generated subsets.
+    ///
+    /// The placeholders `{family}`, `{style}`, `{weight}`, `{version}`, `{subset}` and `{hash}` are
+    /// supported, and both `{subset}` and `{hash}` must be present.
+    pub fn file_name_template(&mut self, template: &str) -> Result<&mut Self> {
+        validate_file_name_template(template)?;
+        self.file_name_template = Some(template.into());
+        Ok(self)
+    }
+
+    /// Restricts the OpenType layout rules kept in the generated subsets to the given scripts
+    /// (such as `latn` or `dev2`). This can greatly reduce the size of fonts whose `GSUB` and
+    /// `GPOS` tables cover many writing systems.
+    ///
+    /// The rules for the default script (`DFLT`) are always kept.
+    pub fn layout_scripts(
+        &mut self,
+        scripts: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<&mut Self> {
+        let list = self.layout_scripts.get_or_insert_with(Vec::new);
+        for script in scripts {
+            list.push(parse_script_tag(script.as_ref())?);
+        }
+        Ok(self)
+    }
+
+    /// Sets the Unicode general categories (as abbreviations such as `Cc` or `Cf`) whose
+    /// characters are never included in the generated subsets.
+    ///
+    /// By default, only control characters (`Cc`) are dropped. Format characters (`Cf`) such as
+    /// the zero-width joiner are kept, as they are needed to shape emoji and many scripts.
+    pub fn drop_categories(
+        &mut self,
+        categories: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<&mut Self> {
+        let mut list = Vec::new();
+        for category in categories {
+            list.push(parse_general_category(category.as_ref())?);
+        }
+        self.dropped_categories = list;
+        Ok(self)
+    }
+
     /// Adds a subset spec statement to this plan.
     pub fn subset_spec(&mut self, spec: &str) -> &mut Self {
         self.subset_specs.push(spec.to_string());
         self
     }
 
+    /// Includes every character in a UTF-8 text file in all fonts, and enables subsetting.
+    ///
+    /// This is equivalent to the `*:@<file path>` subset spec, but fails with an error naming the
+    /// file if it is not valid UTF-8. It may be called multiple times to combine several files.
+    pub fn text_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self> {
+        let path = path.as_ref();
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) => bail!("Could not read text file '{}': {e}", path.display()),
+        };
+        let text = match String::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => bail!(
+                "Text file '{}' is not valid UTF-8: invalid data at byte {}.",
+                path.display(),
+                e.utf8_error().valid_up_to(),
+            ),
+        };
+        self.target_text.extend(text.chars());
+        self.flags.insert(FontFlags::DoSubsetting);
+        Ok(self)
+    }
+
+    /// Loads the subsets used by the Google Fonts splitter from a file, in place of the builtin
+    /// Google Fonts subsets. See [`WebfontData::load_custom`] for the format of the file.
+    ///
+    /// Unlike the builtin subsets, every custom subset is kept whenever a font contains any of its
+    /// characters, and they are applied in the order they are declared, so the first subset a
+    /// font covers is treated as its base subset. Characters not in any custom subset are split
+    /// into `misc` subsets as usual.
+    ///
+    /// Custom subsets replace the subsets for language tags and the grouping of joining scripts
+    /// such as Arabic, so a joining script should be declared as a single subset to be shaped
+    /// correctly.
+    pub fn custom_subsets(&mut self, path: impl AsRef<Path>) -> Result<&mut Self> {
+        self.custom_subsets = Some(Arc::new(WebfontData::load_custom(path.as_ref())?));
+        Ok(self)
+    }
+
     pub fn build(&self) -> LoadedSplitterPlan {
         LoadedSplitterPlan(Arc::new(SplitterPlanData {
             family_config: self.family_config.clone(),
             flags: self.flags,
             subset_specs: self.subset_specs.clone(),
+            target_text: self.target_text.clone(),
+            file_name_template: self.file_name_template.clone(),
+            layout_scripts: self.layout_scripts.as_ref().map(|x| x.as_slice().into()),
+            kept_axes: self.kept_axes.as_ref().map(|x| x.as_slice().into()),
+            inline_below: self.inline_below,
+            high_priority_ratio: self.high_priority_ratio,
+            min_glyphs_to_split: self.min_glyphs_to_split,
+            family_suffix: self.family_suffix.clone(),
+            family_media: self.family_media.clone(),
+            font_display: self.font_display,
+            single_stack: self.single_stack.clone(),
+            dropped_categories: self.dropped_categories.as_slice().into(),
+            custom_subsets: self.custom_subsets.clone(),
         }))
     }
 }
+
+/// The abbreviations of each Unicode general category.
+const GENERAL_CATEGORIES: &[(&str, GeneralCategory)] = &[
+    ("Lu", GeneralCategory::UppercaseLetter),
+    ("Ll", GeneralCategory::LowercaseLetter),
+    ("Lt", GeneralCategory::TitlecaseLetter),
+    ("Lm", GeneralCategory::ModifierLetter),
+    ("Lo", GeneralCategory::OtherLetter),
+    ("Mn", GeneralCategory::NonspacingMark),
+    ("Mc", GeneralCategory::SpacingMark),
+    ("Me", GeneralCategory::EnclosingMark),
+    ("Nd", GeneralCategory::DecimalNumber),
+    ("Nl", GeneralCategory::LetterNumber),
+    ("No", GeneralCategory::OtherNumber),
+    ("Pc", GeneralCategory::ConnectorPunctuation),
+    ("Pd", GeneralCategory::DashPunctuation),
+    ("Ps", GeneralCategory::OpenPunctuation),
+    ("Pe", GeneralCategory::ClosePunctuation),
+    ("Pi", GeneralCategory::InitialPunctuation),
+    ("Pf", GeneralCategory::FinalPunctuation),
+    ("Po", GeneralCategory::OtherPunctuation),
+    ("Sm", GeneralCategory::MathSymbol),
+    ("Sc", GeneralCategory::CurrencySymbol),
+    ("Sk", GeneralCategory::ModifierSymbol),
+    ("So", GeneralCategory::OtherSymbol),
+    ("Zs", GeneralCategory::SpaceSeparator),
+    ("Zl", GeneralCategory::LineSeparator),
+    ("Zp", GeneralCategory::ParagraphSeparator),
+    ("Cc", GeneralCategory::Control),
+    ("Cf", GeneralCategory::Format),
+    ("Cs", GeneralCategory::Surrogate),
+    ("Co", GeneralCategory::PrivateUse),
+    ("Cn", GeneralCategory::Unassigned),
+];
+
+fn parse_general_category(name: &str) -> Result<GeneralCategory> {
+    match GENERAL_CATEGORIES
+        .iter()
+        .find(|x| x.0.eq_ignore_ascii_case(name.trim()))
+    {
+        Some((_, category)) => Ok(*category),
+        None => bail!("Unknown Unicode general category: {name:?}"),
+    }
+}
@@ -1,12 +1,59 @@
 use anyhow::*;
 use arcstr::ArcStr;
 use mkwebfont_common::{character_set::CharacterSet, hashing::WyHashMap};
-use mkwebfont_extract_web::WebrootInfo;
+use mkwebfont_extract_web::{TextSample, WebrootInfo};
 use mkwebfont_fontops::font_info::{FontFaceSet, FontFaceWrapper, FontId};
 use std::{
     fmt::Debug,
+    io::Read,
     sync::{Arc, LazyLock},
 };
+use tracing::info;
+use unicode_properties::{EmojiStatus, GeneralCategory, UnicodeEmoji, UnicodeGeneralCategory};
+
+/// Returns the set of characters that are displayed as emoji by default.
+fn emoji_presentation_chars() -> &'static CharacterSet {
+    static CHARS: LazyLock<CharacterSet> = LazyLock::new(|| {
+        let mut set = CharacterSet::new();
+        for ch in ('\0'..=char::MAX).filter(|x| {
+            matches!(
+                x.emoji_status(),
+                EmojiStatus::EmojiPresentation
+                    | EmojiStatus::EmojiPresentationAndModifierBase
+                    | EmojiStatus::EmojiPresentationAndEmojiComponent
+                    | EmojiStatus::EmojiPresentationAndModifierAndEmojiComponent
+            )
+        }) {
+            set.insert(ch as u32);
+        }
+        set
+    });
+    &CHARS
+}
+
+/// Returns whether a font is meant for displaying emoji.
+fn is_emoji_font(font: &FontFaceWrapper) -> bool {
+    let emoji_count = font.codepoints_in_set(emoji_presentation_chars()).len();
+    font.font_family().to_lowercase().contains("emoji")
+        || emoji_count * 2 > font.all_codepoints().len()
+}
+
+/// Removes all characters outside the Basic Multilingual Plane from a set.
+fn strip_astral(chars: CharacterSet) -> CharacterSet {
+    let mut out = CharacterSet::new();
+    out.extend(chars.into_iter().filter(|x| *x <= 0xFFFF));
+    out
+}
+
+/// Removes all characters in the given general categories from a set.
+fn strip_categories(chars: CharacterSet, categories: &[GeneralCategory]) -> CharacterSet {
+    let mut out = CharacterSet::new();
+    out.extend(chars.into_iter().filter(|x| match char::from_u32(*x) {
+        Some(ch) => !categories.contains(&ch.general_category()),
+        None => true,
+    }));
+    out
+}
 
 #[derive(Clone, Debug, Default)]
 struct SubsetInfo {
@@ -19,6 +66,9 @@ struct SubsetInfo {
 #[derive(Clone, Debug, Default)]
 pub struct AssignedSubsets {
     disabled: bool,
+    exclude_emoji: bool,
+    bmp_only: bool,
+    dropped_categories: Vec<GeneralCategory>,
     assigned_subsets: WyHashMap<FontId, SubsetInfo>,
     all_subset: CharacterSet,
     all_exclusion: CharacterSet,
@@ -26,6 +76,9 @@ pub struct AssignedSubsets {
 
     fallback_required: CharacterSet,
     fallback_info: WyHashMap<Arc<[ArcStr]>, CharacterSet>,
+
+    lang_groups: Vec<(ArcStr, CharacterSet)>,
+    char_counts: WyHashMap<u32, u64>,
 }
 impl AssignedSubsets {
     pub fn disabled() -> &'static AssignedSubsets {
@@ -42,14 +95,44 @@ impl AssignedSubsets {
         self.assigned_subsets.get(&id).unwrap_or_else(|| &EMPTY)
     }
 
+    /// Excludes characters displayed as emoji by default from all fonts not meant for emoji.
+    pub fn exclude_emoji(&mut self) {
+        self.exclude_emoji = true;
+    }
+
+    /// Excludes all characters outside the Basic Multilingual Plane from all fonts.
+    pub fn bmp_only(&mut self) {
+        self.bmp_only = true;
+    }
+
+    /// Excludes all characters in the given general categories from all fonts.
+    pub fn drop_categories(&mut self, categories: &[GeneralCategory]) {
+        self.dropped_categories = categories.to_vec();
+    }
+
     pub fn get_used_chars(&self, font: &FontFaceWrapper) -> CharacterSet {
-        if self.disabled {
+        let chars = if self.disabled {
             font.all_codepoints().clone()
         } else {
             let info = self.get_subset(font.font_id());
             let subsets = &info.subset | &self.all_subset;
             let excludes = &info.exclusion | &self.all_exclusion;
             (subsets - excludes) & font.all_codepoints()
+        };
+        let chars = if self.exclude_emoji && !is_emoji_font(font) {
+            chars - emoji_presentation_chars()
+        } else {
+            chars
+        };
+        let chars = if self.dropped_categories.is_empty() {
+            chars
+        } else {
+            strip_categories(chars, &self.dropped_categories)
+        };
+        if self.bmp_only {
+            strip_astral(chars)
+        } else {
+            chars
         }
     }
 
@@ -78,6 +161,20 @@ impl AssignedSubsets {
     pub fn get_fallback_info(&self) -> &WyHashMap<Arc<[ArcStr]>, CharacterSet> {
         &self.fallback_info
     }
+
+    /// Returns the characters associated with each language tag, in the order they were declared.
+    pub fn get_lang_groups(&self) -> &[(ArcStr, CharacterSet)] {
+        if self.disabled {
+            &[]
+        } else {
+            &self.lang_groups
+        }
+    }
+
+    /// Returns how many times each character occurs in the text collected from webroots.
+    pub fn get_char_counts(&self) -> &WyHashMap<u32, u64> {
+        &self.char_counts
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -96,6 +193,16 @@ impl SubsetDataBuilder {
     ) -> Result<()> {
         let mut reverse_pass = Vec::new();
 
+        let text = if self.subsets.bmp_only {
+            strip_astral(text)
+        } else {
+            text
+        };
+        let text = if self.subsets.dropped_categories.is_empty() {
+            text
+        } else {
+            strip_categories(text, &self.subsets.dropped_categories)
+        };
         let mut current = text.clone();
         for i in 0..fonts.len() {
             let font = fonts[i].as_ref();
@@ -106,6 +213,9 @@ impl SubsetDataBuilder {
                 fulfilled_codepoints &= font.all_codepoints();
             }
             fulfilled_codepoints &= &current;
+            if self.subsets.exclude_emoji && !font.iter().any(is_emoji_font) {
+                fulfilled_codepoints -= emoji_presentation_chars();
+            }
             let fulfilled_codepoints = fulfilled_codepoints;
 
             for j in 0..font.len() {
@@ -146,18 +256,65 @@ impl SubsetDataBuilder {
         Ok(())
     }
 
+    /// Excludes characters displayed as emoji by default from all fonts not meant for emoji.
+    pub fn exclude_emoji(&mut self) {
+        self.subsets.exclude_emoji();
+    }
+
+    /// Excludes all characters outside the Basic Multilingual Plane from all fonts.
+    pub fn bmp_only(&mut self) {
+        self.subsets.bmp_only();
+    }
+
+    /// Excludes all characters in the given general categories from all fonts.
+    pub fn drop_categories(&mut self, categories: &[GeneralCategory]) {
+        self.subsets.drop_categories(categories);
+    }
+
+    /// Includes the given characters in all fonts.
+    pub fn push_all_text(&mut self, text: &CharacterSet) {
+        self.subsets.all_subset.extend(text);
+    }
+
+    fn push_char_counts(&mut self, sample: &TextSample) {
+        for ch in sample.content.iter().flat_map(|x| x.chars()) {
+            *self.subsets.char_counts.entry(ch as u32).or_default() += 1;
+        }
+    }
+
     fn push_exclusion(&mut self, text: CharacterSet, fonts: &[FontFaceWrapper]) {
         for font in fonts {
             self.get_subset_mut(font.font_id()).exclusion.extend(&text);
         }
     }
 
+    fn push_include(&mut self, text: CharacterSet, fonts: &[FontFaceWrapper]) {
+        for font in fonts {
+            self.get_subset_mut(font.font_id()).subset.extend(&text);
+        }
+    }
+
     fn push_preload(&mut self, text: CharacterSet, fonts: &[FontFaceWrapper]) {
         for font in fonts {
             self.get_subset_mut(font.font_id()).preload.extend(&text);
         }
     }
 
+    fn push_lang(&mut self, tag: &str, text: CharacterSet) -> Result<()> {
+        ensure!(
+            !tag.is_empty() && tag.chars().all(|x| x.is_ascii_alphanumeric() || x == '-'),
+            "Invalid language tag: {tag:?}",
+        );
+        self.subsets.all_subset.extend(&text);
+        let lc_tag = tag.to_lowercase();
+        if let Some((_, chars)) = self.subsets.lang_groups.iter_mut().find(|x| x.0 == lc_tag) {
+            chars.extend(text);
+        } else {
+            self.subsets.lang_groups.push((lc_tag.into(), text));
+        }
+        Ok(())
+    }
+
     fn load_fonts(fonts: &FontFaceSet, spec: &str) -> Result<Vec<FontFaceWrapper>> {
         let mut list = Vec::new();
         for font_name in spec.split(',') {
@@ -232,7 +389,14 @@ impl SubsetDataBuilder {
     }
 
     pub fn push_spec(&mut self, fonts: &FontFaceSet, spec: &str) -> Result<()> {
-        if spec.starts_with("@") {
+        if spec == "@-" {
+            let mut contents = String::new();
+            std::io::stdin().read_to_string(&mut contents)?;
+            // piped input nearly always ends in a newline, so blank lines are skipped
+            for line in contents.lines().filter(|x| !x.trim().is_empty()) {
+                self.push_spec(fonts, line)?;
+            }
+        } else if spec.starts_with("@") {
             let contents = std::fs::read_to_string(&spec[1..])?;
             for line in contents.split('\n') {
                 self.push_spec(fonts, line)?;
@@ -257,6 +421,12 @@ impl SubsetDataBuilder {
                 let (fst, snd) = Self::split_two(spec)?;
                 self.push_preload(Self::load_charset(snd)?, &Self::load_fonts(fonts, fst)?);
             }
+        } else if let Some(spec) = spec.strip_prefix("include:") {
+            let (fst, snd) = Self::split_two(spec)?;
+            self.push_include(Self::load_charset(snd)?, &Self::load_fonts(fonts, fst)?);
+        } else if let Some(spec) = spec.strip_prefix("lang:") {
+            let (fst, snd) = Self::split_two(spec)?;
+            self.push_lang(fst, Self::load_charset(snd)?)?;
         } else {
             if spec.starts_with("*:") {
                 self.subsets
@@ -291,11 +461,50 @@ impl SubsetDataBuilder {
                     chars.insert(ch as u32);
                 }
                 self.push_stack(chars, &list)?;
+                self.push_char_counts(sample);
             }
         }
         Ok(())
     }
 
+    /// Excludes the characters outside the `unicode-range` of the author's `@font-face` rules
+    /// from the fonts of the same family.
+    pub fn push_font_face_ranges(&mut self, fonts: &FontFaceSet, text: &WebrootInfo) {
+        for font in fonts.as_list() {
+            let family = font.font_family().to_lowercase();
+            if let Some(range) = text.font_face_ranges.get(family.as_str()) {
+                let excluded = font.all_codepoints() - range;
+                if !excluded.is_empty() {
+                    info!(
+                        "Excluding {} characters outside the `unicode-range` declared for {font}.",
+                        excluded.len(),
+                    );
+                    self.push_exclusion(excluded, std::slice::from_ref(font));
+                }
+            }
+        }
+    }
+
+    /// Assigns all characters used in the webroot to one font stack, ignoring the font stacks
+    /// found in its CSS.
+    pub fn push_webroot_single_stack(
+        &mut self,
+        fonts: &FontFaceSet,
+        text: &WebrootInfo,
+        stack: &str,
+    ) -> Result<()> {
+        let mut chars = CharacterSet::new();
+        for stack in &text.font_stacks {
+            for sample in &stack.samples {
+                for ch in sample.glyphs().chars() {
+                    chars.insert(ch as u32);
+                }
+                self.push_char_counts(sample);
+            }
+        }
+        self.push_stack(chars, &Self::load_fonts_list(fonts, stack)?)
+    }
+
     pub fn build(self) -> AssignedSubsets {
         self.subsets
     }
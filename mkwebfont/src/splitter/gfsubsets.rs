@@ -1,18 +1,19 @@
 use crate::{
-    plan::{AssignedSubsets, LoadedSplitterPlan},
+    plan::{AssignedSubsets, FontFlags, LoadedSplitterPlan},
     splitter::SplitterImplementation,
 };
 use anyhow::Result;
-use mkwebfont_common::character_set::CharacterSet;
+use mkwebfont_common::{character_set::CharacterSet, hashing::WyHashMap};
 use mkwebfont_fontops::{
     font_info::FontFaceWrapper,
     gfonts::gfonts_subsets::{WebfontData, WebfontSubset, WebfontSubsetGroup},
     subsetter::FontEncoder,
 };
 use ordered_float::OrderedFloat;
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, ops::RangeInclusive, sync::Arc};
 use tracing::debug;
 use unicode_blocks::find_unicode_block;
+use unicode_properties::{GeneralCategory, UnicodeGeneralCategory};
 
 #[derive(Copy, Clone, Debug)]
 pub struct TuningParameters {
@@ -35,10 +36,66 @@ const DEFAULT_TUNING: TuningParameters = TuningParameters {
     residual_class_max_size: 200,
 };
 
-struct SplitterState {
+/// Scripts where letters join with their neighbours. These are always kept in a single subset
+/// named after the matching Google Fonts subset, as text split between several `@font-face`
+/// rules cannot be shaped together by the browser.
+const JOINING_SCRIPTS: &[(&str, &[RangeInclusive<u32>])] = &[
+    ("arabic", &[
+        0x0600..=0x06FF,
+        0x0750..=0x077F,
+        0x0870..=0x089F,
+        0x08A0..=0x08FF,
+        0xFB50..=0xFDFF,
+        0xFE70..=0xFEFF,
+    ]),
+    ("syriac", &[0x0700..=0x074F, 0x0860..=0x086F]),
+    ("nko", &[0x07C0..=0x07FF]),
+    ("mandaic", &[0x0840..=0x085F]),
+    ("mongolian", &[0x1800..=0x18AF, 0x11660..=0x1167F]),
+    ("hanifi-rohingya", &[0x10D00..=0x10D3F]),
+    ("sogdian", &[0x10F30..=0x10F6F]),
+    ("adlam", &[0x1E900..=0x1E95F]),
+];
+
+/// Right-to-left scripts, with the ranges used to detect them. When most of the text collected is
+/// in one of these scripts, its subset is given high priority instead of the Latin subsets.
+const RTL_SCRIPTS: &[(&str, RangeInclusive<u32>)] = &[
+    ("hebrew", 0x0590..=0x05FF),
+    ("arabic", 0x0600..=0x06FF),
+    ("syriac", 0x0700..=0x074F),
+    ("thaana", 0x0780..=0x07BF),
+    ("nko", 0x07C0..=0x07FF),
+];
+
+/// Returns the right-to-left script most of the given text is written in, if any.
+///
+/// Each character is weighted by how often it occurs in the text collected from webroots, so a
+/// site written in a right-to-left script is not outweighed by the larger alphabet of a Latin
+/// navigation bar. Characters that never occur in the collected text are counted once.
+fn dominant_rtl_script(
+    chars: &CharacterSet,
+    counts: &WyHashMap<u32, u64>,
+) -> Option<&'static &'static str> {
+    let weight = |filter: &dyn Fn(char) -> bool| -> u64 {
+        chars
+            .chars()
+            .filter(|x| filter(*x))
+            .map(|x| counts.get(&(x as u32)).copied().unwrap_or(1))
+            .sum()
+    };
+    let latin = weight(&|x| matches!(x as u32, 0x41..=0x24F) && x.is_alphabetic());
+    RTL_SCRIPTS
+        .iter()
+        .map(|(name, range)| (name, weight(&|x| range.contains(&(x as u32)))))
+        .filter(|x| x.1 > latin)
+        .max_by_key(|x| x.1)
+        .map(|x| x.0)
+}
+
+struct SplitterState<'a> {
     font: FontFaceWrapper,
     tuning: TuningParameters,
-    data: &'static WebfontData,
+    data: &'a WebfontData,
 
     fulfilled_codepoints: CharacterSet,
     preload_codepoints: CharacterSet,
@@ -46,23 +103,61 @@ struct SplitterState {
     processed_groups: HashSet<Arc<str>>,
     misc_idx: usize,
     preload_done: bool,
+    count_visible_only: bool,
 }
-impl SplitterState {
-    async fn init(font: &FontFaceWrapper, assigned: &AssignedSubsets) -> Result<SplitterState> {
+impl<'a> SplitterState<'a> {
+    async fn init(
+        font: &FontFaceWrapper,
+        plan: &'a LoadedSplitterPlan,
+        assigned: &AssignedSubsets,
+    ) -> Result<SplitterState<'a>> {
         let fulfilled = font.all_codepoints() - assigned.get_used_chars(font);
+        let mut tuning = DEFAULT_TUNING;
+        if let Some(ratio) = plan.high_priority_ratio {
+            tuning.high_priority_ratio_threshold = ratio;
+        }
+        if plan.flags.contains(FontFlags::DoSubsetting) {
+            if let Some(script) =
+                dominant_rtl_script(&assigned.get_used_chars(font), assigned.get_char_counts())
+            {
+                debug!("Text is mostly in a right-to-left script, prioritizing subset: {script}");
+                tuning.high_priority_subsets = std::slice::from_ref(script);
+            }
+        }
         Ok(SplitterState {
             font: font.clone(),
-            tuning: DEFAULT_TUNING,
-            data: WebfontData::load(),
+            tuning,
+            data: match &plan.custom_subsets {
+                Some(data) => data,
+                None => WebfontData::load(),
+            },
             fulfilled_codepoints: fulfilled,
             preload_codepoints: assigned.get_preload_chars(font),
             processed_subsets: Default::default(),
             processed_groups: Default::default(),
             misc_idx: 0,
             preload_done: false,
+            count_visible_only: plan.flags.contains(FontFlags::CountVisibleOnly),
         })
     }
 
+    /// Returns the number of codepoints in a set that count towards the subset thresholds.
+    fn threshold_count(&self, codepoints: &CharacterSet) -> usize {
+        if self.count_visible_only {
+            codepoints
+                .chars()
+                .filter(|x| {
+                    !matches!(
+                        x.general_category(),
+                        GeneralCategory::SpaceSeparator | GeneralCategory::Format
+                    )
+                })
+                .count()
+        } else {
+            codepoints.len()
+        }
+    }
+
     /// Applies a single subset
     fn do_subset(&mut self, subset: &WebfontSubset, encoder: &mut FontEncoder, never_reject: bool) {
         if !self.processed_subsets.contains(&subset.name) {
@@ -72,8 +167,11 @@ impl SplitterState {
             let mut new_codepoints =
                 self.font.codepoints_in_set(&subset.map) - &self.fulfilled_codepoints;
 
-            if never_reject || new_codepoints.len() as usize >= self.tuning.reject_subset_threshold
+            if never_reject
+                || self.threshold_count(&new_codepoints) >= self.tuning.reject_subset_threshold
             {
+                // the first subset accepted holds the most commonly used characters
+                let is_base = !self.preload_done;
                 if !self.preload_done {
                     let new = new_codepoints.clone() | &self.preload_codepoints;
                     if new != new_codepoints {
@@ -88,7 +186,11 @@ impl SplitterState {
                 }
 
                 self.fulfilled_codepoints.extend(new_codepoints.clone());
-                encoder.add_subset(&name, new_codepoints);
+                if is_base {
+                    encoder.add_base_subset(&name, new_codepoints);
+                } else {
+                    encoder.add_subset(&name, new_codepoints);
+                }
             } else {
                 debug!("Rejecting subset: {name} (unique codepoints: {})", new_codepoints.len())
             }
@@ -184,6 +286,55 @@ impl SplitterState {
         }
     }
 
+    /// Applies the subsets for each language declared in the subset data. Characters shared by
+    /// several languages are placed in the subset of the first language declared.
+    fn apply_lang_groups(&mut self, assigned: &AssignedSubsets, encoder: &mut FontEncoder) {
+        for (tag, chars) in assigned.get_lang_groups() {
+            let new_codepoints = self.font.codepoints_in_set(chars) - &self.fulfilled_codepoints;
+            if !new_codepoints.is_empty() {
+                debug!("Applying language subset: {tag}");
+                let subset =
+                    WebfontSubset { name: format!("lang-{tag}").into(), map: new_codepoints };
+                self.do_subset(&subset, encoder, true);
+            }
+        }
+    }
+
+    /// Keeps the characters of a joining script in the font together in a single subset.
+    fn apply_joining_script(
+        &mut self,
+        name: &str,
+        ranges: &[RangeInclusive<u32>],
+        encoder: &mut FontEncoder,
+    ) {
+        let mut chars = CharacterSet::new();
+        for range in ranges {
+            chars.extend(range.clone());
+        }
+        let new_codepoints = self.font.codepoints_in_set(&chars) - &self.fulfilled_codepoints;
+        if !new_codepoints.is_empty() {
+            debug!("Applying joining script subset: {name}");
+            let subset = WebfontSubset { name: name.into(), map: new_codepoints };
+            self.do_subset(&subset, encoder, true);
+        }
+    }
+
+    /// Keeps the characters of each joining script in the font together in a single subset.
+    fn apply_joining_scripts(&mut self, encoder: &mut FontEncoder) {
+        for (name, ranges) in JOINING_SCRIPTS {
+            self.apply_joining_script(name, ranges, encoder);
+        }
+    }
+
+    /// Applies every custom subset the font contains characters from, in the order declared.
+    fn apply_custom_subsets(&mut self, encoder: &mut FontEncoder) {
+        for subset in &self.data.subsets {
+            if self.unique_available_count(subset) > 0 {
+                self.do_subset(subset, encoder, true);
+            }
+        }
+    }
+
     /// Applies high priority subsets immediately.
     fn check_high_priority(&mut self, encoder: &mut FontEncoder) {
         for &name in self.tuning.high_priority_subsets {
@@ -192,7 +343,11 @@ impl SplitterState {
                 let subset = self.data.by_name.get(name).unwrap().clone();
                 if self.unique_available_ratio(&subset) > self.tuning.high_priority_ratio_threshold
                 {
-                    self.do_subset(&subset, encoder, false);
+                    if let Some((_, ranges)) = JOINING_SCRIPTS.iter().find(|x| x.0 == name) {
+                        self.apply_joining_script(name, ranges, encoder);
+                    } else {
+                        self.do_subset(&subset, encoder, false);
+                    }
                 }
             }
         }
@@ -302,19 +457,90 @@ impl SplitterImplementation for GfSubsetSplitter {
     async fn split(
         &self,
         font: &FontFaceWrapper,
-        _plan: &LoadedSplitterPlan,
+        plan: &LoadedSplitterPlan,
         assigned: &AssignedSubsets,
         encoder: &mut FontEncoder,
     ) -> Result<()> {
-        let mut ctx = SplitterState::init(font, assigned).await?;
-        ctx.check_high_priority(encoder);
-        while let Some(subset_group) = ctx.select_subset_group() {
-            ctx.do_subset_group(&subset_group, encoder);
-        }
-        while let Some(subset) = ctx.select_next_subset() {
-            ctx.do_subset(&subset, encoder, false);
+        let mut ctx = SplitterState::init(font, plan, assigned).await?;
+        if plan.custom_subsets.is_some() {
+            // custom subsets replace the builtin grouping, so the first one stays the base subset
+            ctx.apply_custom_subsets(encoder);
+        } else {
+            ctx.check_high_priority(encoder);
+            ctx.apply_lang_groups(assigned, encoder);
+            ctx.apply_joining_scripts(encoder);
+            while let Some(subset_group) = ctx.select_subset_group() {
+                ctx.do_subset_group(&subset_group, encoder);
+            }
+            while let Some(subset) = ctx.select_next_subset() {
+                ctx.do_subset(&subset, encoder, false);
+            }
         }
         ctx.split_resiudal(encoder);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_webfont, LoadedFontSetBuilder, SplitterPlan};
+
+    fn count_text(text: &[&str]) -> (CharacterSet, WyHashMap<u32, u64>) {
+        let mut chars = CharacterSet::new();
+        let mut counts = WyHashMap::default();
+        for ch in text.iter().flat_map(|x| x.chars()) {
+            chars.insert(ch as u32);
+            *counts.entry(ch as u32).or_default() += 1;
+        }
+        (chars, counts)
+    }
+
+    #[test]
+    fn hebrew_site_with_latin_navigation() {
+        let article = "שלום עולם, זהו מאמר ארוך שנכתב בעברית כדי לבדוק את כיוון הטקסט באתר. ";
+        let mut text = vec!["Home About Blog Projects Contact Privacy Jquery Wiki FAQ Login"];
+        text.extend(std::iter::repeat_n(article, 20));
+        let (chars, counts) = count_text(&text);
+        assert_eq!(dominant_rtl_script(&chars, &counts), Some(&"hebrew"));
+    }
+
+    #[test]
+    fn latin_site_with_hebrew_quote() {
+        let article = "The quick brown fox jumps over the lazy dog, as it often does. ";
+        let mut text = vec!["שלום עולם"];
+        text.extend(std::iter::repeat_n(article, 20));
+        let (chars, counts) = count_text(&text);
+        assert_eq!(dominant_rtl_script(&chars, &counts), None);
+    }
+
+    #[tokio::test]
+    async fn custom_subsets_keep_first_subset_as_base() {
+        let dir = std::env::temp_dir().join(format!("mkwebfont-custom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("subsets.toml");
+        std::fs::write(
+            &config,
+            "[[subset]]\nname = \"latin\"\nranges = [\"U+0020-007E\"]\n\n\
+             [[subset]]\nname = \"arabic\"\nranges = [\"U+0600-06FF\"]\n",
+        )
+        .unwrap();
+
+        let font = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/DejaVuSans.ttf");
+        let fonts = LoadedFontSetBuilder::new()
+            .load_from_disk([font])
+            .build()
+            .await
+            .unwrap();
+        let mut plan = SplitterPlan::new();
+        plan.gfonts_splitter().custom_subsets(&config).unwrap();
+        let results = process_webfont(&plan, &fonts, None).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let subsets = results.webfonts[0].subsets();
+        let base: Vec<_> = subsets.iter().filter(|x| x.is_base()).collect();
+        assert_eq!(base.len(), 1);
+        assert_eq!(base[0].name(), "latin");
+        assert!(subsets.iter().any(|x| x.name() == "arabic"));
+    }
+}
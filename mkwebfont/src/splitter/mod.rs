@@ -2,15 +2,15 @@ use crate::{
     plan::{AssignedSubsets, FontFlags, LoadedSplitterPlan, SubsetDataBuilder},
     WebfontInfo,
 };
-use anyhow::Result;
-use mkwebfont_common::join_set::JoinSet;
+use anyhow::{bail, Result};
+use mkwebfont_common::{character_set::CharacterSet, join_set::JoinSet};
 use mkwebfont_fontops::{
-    font_info::{FontFaceSet, FontFaceWrapper},
+    font_info::{FontFaceSet, FontFaceWrapper, SubsetSettings},
     gfonts::fallback_info::FallbackInfo,
     subsetter::FontEncoder,
 };
 use std::sync::Arc;
-use tracing::{info, info_span};
+use tracing::{debug, info, info_span, warn};
 use tracing_futures::Instrument;
 
 mod gfsubsets;
@@ -34,21 +34,89 @@ impl SplitterImplementation for NullSplitter {
         assigned: &AssignedSubsets,
         encoder: &mut FontEncoder,
     ) -> Result<()> {
-        encoder.add_subset("all", assigned.get_used_chars(font));
+        encoder.add_base_subset("all", assigned.get_used_chars(font));
         Ok(())
     }
 }
 
+/// Returns whether a font appears to be an icon font, based on most of its codepoints being in the
+/// private use areas.
+///
+/// Whether the font's characters are only used through the CSS `content` property is not checked,
+/// as the extractor does not record where the text it collects comes from.
+fn is_icon_font(font: &FontFaceWrapper) -> bool {
+    let private_use = font
+        .all_codepoints()
+        .iter()
+        .filter(|x| matches!(x, 0xE000..=0xF8FF | 0xF0000..=0xFFFFD | 0x100000..=0x10FFFD))
+        .count();
+    private_use * 2 > font.all_codepoints().len()
+}
+
+/// Checks whether the embedding permissions of a font allow generating webfonts from it.
+fn check_fs_type(plan: &LoadedSplitterPlan, font: &FontFaceWrapper) -> Result<()> {
+    let classification = font.classification();
+    let problem = if !classification.embeddable() {
+        "does not allow embedding"
+    } else if !classification.allows_subsetting() {
+        "does not allow subsetting"
+    } else {
+        return Ok(());
+    };
+    let fs_type = classification.fs_type;
+    if plan.flags.contains(FontFlags::RespectFsType) {
+        bail!("{font} {problem} (fsType: 0x{fs_type:04x}).");
+    }
+    warn!(
+        "{font} {problem} (fsType: 0x{fs_type:04x}). Generating webfonts from it may violate its \
+         license!"
+    );
+    Ok(())
+}
+
+/// Returns the settings used to subset fonts for a plan.
+fn subset_settings(plan: &LoadedSplitterPlan) -> SubsetSettings {
+    SubsetSettings {
+        preserve_all_axes: plan.flags.contains(FontFlags::PreserveAllAxes),
+        kept_axes: plan.kept_axes.clone(),
+        file_name_template: plan.file_name_template.clone(),
+        layout_scripts: plan.layout_scripts.clone(),
+        nested_store: plan.flags.contains(FontFlags::NestedStore),
+        prune_mark_positioning: plan.flags.contains(FontFlags::PruneMarkPositioning),
+        retain_notdef_outline: plan.flags.contains(FontFlags::RetainNotdefOutline),
+        strip_hinting: plan.flags.contains(FontFlags::StripHinting),
+        emit_woff1: plan.flags.contains(FontFlags::EmitWoff1),
+        per_subset_hashing: plan.flags.contains(FontFlags::PerSubsetHashing),
+    }
+}
+
 /// The internal function that actually splits the webfont.
 pub async fn split_webfont(
     plan: &LoadedSplitterPlan,
     assigned: &AssignedSubsets,
     font: &FontFaceWrapper,
 ) -> Result<WebfontInfo> {
-    let mut encoder = FontEncoder::new(font.clone(), assigned.get_range_exclusion(font));
+    check_fs_type(plan, font)?;
+    let settings = subset_settings(plan);
+    let mut encoder = FontEncoder::new(font.clone(), assigned.get_range_exclusion(font), settings);
 
     if !assigned.get_used_chars(font).is_empty() {
-        if plan.flags.contains(FontFlags::NoSplitter) {
+        let is_small = font.all_codepoints().len() < plan.min_glyphs_to_split;
+        if is_small && !plan.flags.contains(FontFlags::NoSplitter) {
+            info!(
+                "Font only covers {} codepoints, fewer than the {} needed to split it. Not splitting.",
+                font.all_codepoints().len(),
+                plan.min_glyphs_to_split,
+            );
+        }
+        let is_icon = !plan.flags.contains(FontFlags::SplitIconFonts) && is_icon_font(font);
+        if is_icon && !is_small && !plan.flags.contains(FontFlags::NoSplitter) {
+            debug!(
+                "Font mostly covers private use codepoints, and appears to be an icon font. \
+                 Not splitting."
+            );
+        }
+        if plan.flags.contains(FontFlags::NoSplitter) || is_small || is_icon {
             NullSplitter
                 .split(font, plan, assigned, &mut encoder)
                 .await?
@@ -81,13 +149,39 @@ pub const FALLBACK_FONT_NAME: &str = "mkwebfontFallbackV1";
 pub async fn make_fallback_font(
     plan: &LoadedSplitterPlan,
     assigned: &AssignedSubsets,
+    primary_coverage: &CharacterSet,
 ) -> Result<Vec<WebfontInfo>> {
-    let chars = assigned.get_fallback_chars().clone();
-    info!("Characters for fallback: {:?}", chars.debug_str());
+    let mut chars = assigned.get_fallback_chars().clone();
+    let forced = plan.flags.contains(FontFlags::ForceFallback);
+    if forced {
+        chars |= FallbackInfo::all_codepoints();
+    }
+    if !primary_coverage.is_empty() {
+        let redundant = chars.clone() & primary_coverage;
+        if !redundant.is_empty() {
+            info!("Removing {} characters covered by other fonts from fallback.", redundant.len());
+            chars -= redundant;
+        }
+    }
+    if forced {
+        info!("Characters for fallback: {} codepoints", chars.len());
+    } else {
+        info!("Characters for fallback: {:?}", chars.debug_str());
+    }
     if chars.is_empty() {
         Ok(Vec::new())
     } else {
-        let needed_fonts = FallbackInfo::load_needed_fonts(&chars).await?;
+        let needed_fonts = match FallbackInfo::load_needed_fonts(&chars).await {
+            Ok(fonts) => fonts,
+            Err(e)
+                if !plan.flags.contains(FontFlags::Strict)
+                    && !plan.flags.contains(FontFlags::RequireFullCoverage) =>
+            {
+                warn!("Could not load fallback fonts, no fallback font will be generated: {e}");
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
         let font_set = FontFaceSet::build(needed_fonts.into_iter());
         let fallback_stack = FallbackInfo::build_stack(&chars);
 
@@ -99,6 +193,20 @@ pub async fn make_fallback_font(
         assigned.push_stack(chars.clone(), &stack_fonts)?;
         let assigned = Arc::new(assigned.build());
 
+        let uncovered = assigned.get_fallback_chars();
+        if !uncovered.is_empty() {
+            let list = uncovered
+                .iter_sorted()
+                .map(|x| format!("U+{x:04X}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if plan.flags.contains(FontFlags::RequireFullCoverage) {
+                bail!("{} characters are not covered by any font: {list}", uncovered.len());
+            } else {
+                warn!("{} characters are not covered by any font: {list}", uncovered.len());
+            }
+        }
+
         let mut joins = JoinSet::new();
         for font in font_set.as_list() {
             let assigned = assigned.clone();
@@ -110,7 +218,7 @@ pub async fn make_fallback_font(
 
             joins.spawn(
                 async move {
-                    let mut encoder = FontEncoder::new(font.clone(), chars);
+                    let mut encoder = FontEncoder::new(font.clone(), chars, subset_settings(&plan));
 
                     gfsubsets::GfSubsetSplitter
                         .split(&font, &plan, &*assigned, &mut encoder)
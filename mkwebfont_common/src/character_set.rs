@@ -1,22 +1,30 @@
 use crate::hashing::WyHashSet;
 use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::hash_set::{IntoIter, Iter},
     fmt::{Debug, Formatter},
     ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Sub, SubAssign},
 };
 
+/// A set of Unicode codepoints.
+///
+/// Sets can be combined with the `&` (intersection), `|` (union), `^` (symmetric difference) and
+/// `-` (difference) operators, along with their assigning forms, on both owned and borrowed sets.
 #[derive(Clone, Eq, PartialEq, Default)]
 pub struct CharacterSet(WyHashSet<u32>);
 impl CharacterSet {
+    /// Creates an empty set.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Adds a codepoint to the set, returning whether it was not already present.
     pub fn insert(&mut self, character: u32) -> bool {
         self.0.insert(character)
     }
 
+    /// Returns whether this set has any codepoint in common with another set.
     pub fn intersects(&self, other: &CharacterSet) -> bool {
         for char in &self.0 {
             if other.contains(*char) {
@@ -26,38 +34,65 @@ impl CharacterSet {
         false
     }
 
+    /// Returns the number of codepoints this set has in common with another set, without
+    /// allocating their intersection.
+    pub fn intersection_len(&self, other: &CharacterSet) -> usize {
+        let (small, large) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small.iter().filter(|x| large.contains(*x)).count()
+    }
+
+    /// Removes a codepoint from the set, returning whether it was present.
     pub fn remove(&mut self, character: u32) -> bool {
         self.0.remove(&character)
     }
 
+    /// Returns whether the set contains a codepoint.
     pub fn contains(&self, character: u32) -> bool {
         self.0.contains(&character)
     }
 
+    /// Returns whether the set contains a character.
+    pub fn contains_char(&self, character: char) -> bool {
+        self.contains(character as u32)
+    }
+
+    /// Returns the number of codepoints in the set.
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
+    /// Returns whether the set contains no codepoints.
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
 
+    /// Returns the lowest codepoint in the set.
     pub fn min(&self) -> Option<u32> {
         self.0.iter().min().cloned()
     }
 
+    /// Returns the characters in the set as a string, in codepoint order.
     pub fn debug_str(&self) -> String {
-        self.iter_sorted()
-            .map(|x| char::from_u32(x).unwrap())
-            .collect()
+        self.iter_sorted().filter_map(char::from_u32).collect()
     }
 
-    /// Returns an iterator.
+    /// Returns an iterator over the codepoints in this set, in no particular order.
     pub fn iter(&self) -> CharacterSetIter {
         CharacterSetIter(self.0.iter())
     }
 
-    /// Returns an iterator.
+    /// Returns an iterator over the characters in this set as [`char`]s.
+    ///
+    /// Values that are not valid Unicode scalar values are skipped.
+    pub fn chars(&self) -> CharacterSetChars<'_> {
+        CharacterSetChars(self.0.iter())
+    }
+
+    /// Returns an iterator over the codepoints in this set, in ascending order.
     pub fn iter_sorted(&self) -> std::vec::IntoIter<u32> {
         let mut vec: Vec<_> = self.iter().collect();
         vec.sort();
@@ -116,6 +151,21 @@ impl Extend<u32> for CharacterSet {
         self.0.extend(iter)
     }
 }
+impl Extend<char> for CharacterSet {
+    fn extend<T: IntoIterator<Item = char>>(&mut self, iter: T) {
+        self.0.extend(iter.into_iter().map(|x| x as u32))
+    }
+}
+impl FromIterator<u32> for CharacterSet {
+    fn from_iter<T: IntoIterator<Item = u32>>(iter: T) -> Self {
+        CharacterSet(iter.into_iter().collect())
+    }
+}
+impl FromIterator<char> for CharacterSet {
+    fn from_iter<T: IntoIterator<Item = char>>(iter: T) -> Self {
+        CharacterSet(iter.into_iter().map(|x| x as u32).collect())
+    }
+}
 
 impl<'a> BitAndAssign<&'a CharacterSet> for CharacterSet {
     fn bitand_assign(&mut self, rhs: &'a CharacterSet) {
@@ -206,6 +256,15 @@ impl<'a> Iterator for CharacterSetIter<'a> {
     }
 }
 
+/// The [`char`] iterator for [`CharacterSet`]
+pub struct CharacterSetChars<'a>(Iter<'a, u32>);
+impl<'a> Iterator for CharacterSetChars<'a> {
+    type Item = char;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.by_ref().find_map(|x| char::from_u32(*x))
+    }
+}
+
 /// The owned iterator for [`CharacterSet`]
 pub struct CharacterSetIntoIter(IntoIter<u32>);
 impl Iterator for CharacterSetIntoIter {
@@ -219,7 +278,8 @@ impl Iterator for CharacterSetIntoIter {
 ///
 /// This does not take less memory, but compresses better, as it is delta encoded and not in a
 /// random order as a [`HashSet`] would be.
-#[derive(Clone, Encode, Decode)]
+#[derive(Clone, Encode, Decode, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct CompressedCharacterSet(Vec<u32>);
 impl Debug for CompressedCharacterSet {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -1,18 +1,25 @@
 use crate::hashing::{raw_hash, to_nix_base32, RawHash, WyHashBuilder};
 use anyhow::{bail, Result};
-use bincode::{Decode, Encode};
+use bincode::{config::standard, Decode, Encode};
 use std::{
     collections::HashMap,
     fmt::{Debug, Formatter},
+    fs::File,
     io::Read,
     path::{Path, PathBuf},
-    sync::{Arc, LazyLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+    time::Duration,
 };
-use tokio::sync::{Mutex, OnceCell};
+use tokio::sync::{Mutex, OnceCell, Semaphore};
 use tracing::{info, warn};
 
-static CACHE: LazyLock<Mutex<HashMap<RawHash, Arc<OnceCell<Arc<[u8]>>>, WyHashBuilder>>> =
-    LazyLock::new(|| Mutex::new(HashMap::default()));
+/// Files loaded in this run, shared between concurrent loads of the same file.
+type LoadCache<K> = Mutex<HashMap<K, Arc<OnceCell<Arc<[u8]>>>, WyHashBuilder>>;
+
+static CACHE: LazyLock<LoadCache<RawHash>> = LazyLock::new(|| Mutex::new(HashMap::default()));
 static APPIMAGE_DIR: LazyLock<Option<PathBuf>> =
     LazyLock::new(|| std::env::var_os("MKWEBFONT_APPIMAGE_DATA").map(PathBuf::from));
 static CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
@@ -28,6 +35,163 @@ static CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
     cache_dir
 });
 
+/// The number of times a download is retried after being rate limited.
+const MAX_RETRIES: u32 = 5;
+
+static DOWNLOAD_CONCURRENCY: AtomicUsize = AtomicUsize::new(8);
+static DOWNLOAD_PERMITS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(DOWNLOAD_CONCURRENCY.load(Ordering::Relaxed)));
+
+/// Sets the maximum number of files that are downloaded at once. Defaults to 8.
+///
+/// This must be called before any file is downloaded to have an effect.
+pub fn set_download_concurrency(limit: usize) {
+    DOWNLOAD_CONCURRENCY.store(limit.max(1), Ordering::Relaxed);
+}
+
+/// A downloaded file, along with the validators used to check whether it changed.
+struct Fetched {
+    status: u16,
+    data: Vec<u8>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Downloads a file with the given request headers, reading at most `limit` bytes if given.
+///
+/// This respects the download concurrency limit, and retries when rate limited.
+async fn fetch_with(url: &str, limit: Option<u64>, headers: &[(&str, &str)]) -> Result<Fetched> {
+    let _permit = DOWNLOAD_PERMITS.acquire().await?;
+    info!("Downloading '{url}'...");
+
+    let mut delay = Duration::from_secs(1);
+    let mut retries = 0;
+    loop {
+        let mut req = ureq::get(url);
+        for (name, value) in headers {
+            req = req.set(name, value);
+        }
+        match req.call() {
+            Ok(req) => {
+                let status = req.status();
+                let etag = req.header("ETag").map(|x| x.to_string());
+                let last_modified = req.header("Last-Modified").map(|x| x.to_string());
+                let mut data = Vec::new();
+                req.into_reader()
+                    .take(limit.unwrap_or(u64::MAX))
+                    .read_to_end(&mut data)?;
+                return Ok(Fetched { status, data, etag, last_modified });
+            }
+            Err(ureq::Error::Status(429, resp)) if retries < MAX_RETRIES => {
+                let wait = resp
+                    .header("Retry-After")
+                    .and_then(|x| x.parse().ok())
+                    .map_or(delay, Duration::from_secs);
+                warn!("Rate limited downloading '{url}', retrying in {wait:?}...");
+                tokio::time::sleep(wait).await;
+                delay *= 2;
+                retries += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Downloads a file, reading at most `limit` bytes if given.
+///
+/// This respects the download concurrency limit, and retries when rate limited.
+async fn fetch(url: &str, limit: Option<u64>) -> Result<Vec<u8>> {
+    Ok(fetch_with(url, limit, &[]).await?.data)
+}
+
+/// A file downloaded by [`download_url`], as stored in the download cache.
+#[derive(Encode, Decode)]
+struct CachedUrl {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    hash: RawHash,
+    data: Vec<u8>,
+}
+
+/// Reads the cached copy of a URL, if there is a valid one.
+fn read_cached_url(path: &Path, url: &str) -> Option<CachedUrl> {
+    let data = std::fs::read(path).ok()?;
+    match bincode::decode_from_slice::<CachedUrl, _>(&data, standard()) {
+        Ok((cached, _)) if cached.url == url && raw_hash(&cached.data) == cached.hash => {
+            Some(cached)
+        }
+        _ => {
+            warn!("Corrupted cache file: {}", path.display());
+            None
+        }
+    }
+}
+
+/// Downloads a URL, using the copy in `cache_dir` if the server reports it has not changed.
+async fn load_url(cache_dir: &Path, url: &str) -> Result<Arc<[u8]>> {
+    let filename = format!("url.{}.bin", to_nix_base32(&raw_hash(url.as_bytes())));
+    let cache_path = cache_dir.join(&filename);
+    let cached = read_cached_url(&cache_path, url);
+
+    let mut headers = Vec::new();
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            headers.push(("If-Modified-Since", last_modified.as_str()));
+        }
+    }
+    let fetched = match (fetch_with(url, None, &headers).await, cached) {
+        (Ok(fetched), Some(cached)) if fetched.status == 304 => return Ok(cached.data.into()),
+        (Ok(fetched), _) => fetched,
+        (Err(e), Some(cached)) => {
+            warn!("Could not check '{url}' for changes, using the cached copy: {e}");
+            return Ok(cached.data.into());
+        }
+        (Err(e), None) => return Err(e),
+    };
+    if fetched.status != 200 {
+        bail!("Unexpected status {} downloading '{url}'.", fetched.status);
+    }
+
+    let cached = CachedUrl {
+        url: url.to_string(),
+        etag: fetched.etag,
+        last_modified: fetched.last_modified,
+        hash: raw_hash(&fetched.data),
+        data: fetched.data,
+    };
+    let cache_tmp_path = cache_dir.join(format!("{filename}.download-tmp-{}", std::process::id()));
+    std::fs::write(&cache_tmp_path, bincode::encode_to_vec(&cached, standard())?)?;
+    std::fs::rename(&cache_tmp_path, &cache_path)?;
+
+    Ok(cached.data.into())
+}
+
+/// Downloads a file whose contents are not known in advance.
+///
+/// The file is stored in the download cache along with its `ETag` and `Last-Modified` headers,
+/// and later downloads of the same URL only fetch it again if the server reports that it changed.
+/// If the server cannot be reached, the cached copy is used. Repeated downloads of the same URL
+/// within one run are shared.
+pub async fn download_url(url: &str) -> Result<Arc<[u8]>> {
+    static URL_CACHE: LazyLock<LoadCache<String>> =
+        LazyLock::new(|| Mutex::new(HashMap::default()));
+
+    let arc = URL_CACHE
+        .lock()
+        .await
+        .entry(url.to_string())
+        .or_default()
+        .clone();
+    let result = arc
+        .get_or_try_init(|| async { load_url(&CACHE_DIR, url).await })
+        .await?;
+    Ok(result.clone())
+}
+
 #[derive(Clone, Encode, Decode, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct DownloadInfo {
     filename_prefix: String,
@@ -57,6 +221,10 @@ impl DownloadInfo {
         })
     }
 
+    async fn download(&self) -> Result<Vec<u8>> {
+        fetch(&self.url, Some(self.size)).await
+    }
+
     async fn raw_load(&self) -> Result<Arc<[u8]>> {
         let filename = format!(
             "{}.{}{}",
@@ -80,6 +248,31 @@ impl DownloadInfo {
         let mut cache_path = CACHE_DIR.to_path_buf();
         cache_path.push(&filename);
 
+        // Hold a lock for this file while checking and filling the cache, so concurrent instances
+        // of mkwebfont sharing a cache directory wait for each other instead of all downloading
+        // it. The lock is released when `lock_file` is dropped.
+        //
+        // Waiting for the lock blocks, so it is done outside the async runtime.
+        let mut lock_path = cache_path.clone();
+        lock_path.pop();
+        lock_path.push(format!("{filename}.lock"));
+        let lock_file = {
+            let lock_path = lock_path.clone();
+            tokio::task::spawn_blocking(move || -> Result<File> {
+                let lock_file = File::create(&lock_path)?;
+                lock_file.lock()?;
+                Ok(lock_file)
+            })
+            .await??
+        };
+
+        // The lock file is removed once the cache file is in place. A process that still locks
+        // the removed file afterwards finds the cache file and does not download it again.
+        let release_lock = |lock_file: File| {
+            let _ = std::fs::remove_file(&lock_path);
+            drop(lock_file);
+        };
+
         if cache_path.exists() {
             if !cache_path.is_file() {
                 bail!("Cache directory contains subdirectories!? Just giving up.");
@@ -89,16 +282,15 @@ impl DownloadInfo {
                 warn!("Corrupted cache file: {}", cache_path.display());
                 std::fs::remove_file(&cache_path)?;
             } else {
+                release_lock(lock_file);
                 return Ok(data.into());
             }
         }
 
-        info!("Downloading '{}'...", self.url);
-        let req = ureq::get(&self.url).call()?;
-        let mut file_data = Vec::new();
-        req.into_reader()
-            .take(self.size)
-            .read_to_end(&mut file_data)?;
+        let file_data = self.download().await?;
+        if file_data.len() as u64 != self.size || raw_hash(&file_data) != self.hash {
+            bail!("Downloaded file does not match the expected hash: {}", self.url);
+        }
 
         let mut cache_tmp_path = cache_path.clone();
         cache_tmp_path.pop();
@@ -108,6 +300,7 @@ impl DownloadInfo {
         // This should work even if multiple instances of mkwebfont are trying do this.
         std::fs::write(&cache_tmp_path, &file_data)?;
         std::fs::rename(&cache_tmp_path, &cache_path)?;
+        release_lock(lock_file);
 
         Ok(file_data.into())
     }
@@ -131,3 +324,62 @@ impl Debug for DownloadInfo {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+    };
+
+    /// Serves each of the given HTTP responses to one connection in turn, and returns the URL of
+    /// the server along with the requests it receives.
+    fn serve(responses: Vec<String>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/font.ttf", listener.local_addr().unwrap());
+        let (send, recv) = channel();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                // the requests are not always checked
+                let _ = send.send(String::from_utf8_lossy(&request[..len]).to_string());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, recv)
+    }
+
+    #[tokio::test]
+    async fn rate_limited_download_is_retried() {
+        let (url, _) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\n\
+             Connection: close\r\n\r\n"
+                .to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nfont".to_string(),
+        ]);
+        assert_eq!(fetch(&url, None).await.unwrap(), b"font");
+    }
+
+    #[tokio::test]
+    async fn unchanged_download_is_loaded_from_cache() {
+        let dir = std::env::temp_dir().join(format!("mkwebfont-dl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 4\r\nConnection: close\r\n\r\n\
+             font"
+                .to_string(),
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+
+        assert_eq!(&*load_url(&dir, &url).await.unwrap(), b"font");
+        assert!(!requests.recv().unwrap().contains("If-None-Match"));
+        assert_eq!(&*load_url(&dir, &url).await.unwrap(), b"font");
+        assert!(requests.recv().unwrap().contains("If-None-Match: \"v1\""));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
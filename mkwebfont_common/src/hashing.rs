@@ -1,3 +1,4 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use std::{
     collections::{HashMap, HashSet},
     hash::{BuildHasher, Hash, Hasher},
@@ -78,3 +79,9 @@ pub fn hash_fragment(data: &[u8]) -> String {
     let hash_str = &hash_str[1..21];
     hash_str.to_string()
 }
+
+/// Returns a subresource integrity hash for the data, such as `sha384-...`.
+pub fn integrity_hash(data: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA384, data);
+    format!("sha384-{}", STANDARD.encode(digest.as_ref()))
+}
@@ -25,25 +25,54 @@ pub fn get_relative_fragment(parent: &Path, child: &Path) -> Result<String> {
     }
 }
 
+/// Returns the relative path from a file to a target path, as used in a URI.
+///
+/// The file itself does not need to exist, only the directory containing it.
 pub fn get_relative_from(root: &Path, target: &Path) -> Result<String> {
-    let root = root.canonicalize()?;
+    let root = match root.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize()?,
+        _ => Path::new(".").canonicalize()?,
+    };
     let target = target.canonicalize()?;
 
     let mut accum = root.as_path();
     let mut super_frag = String::new();
     loop {
-        if let Some(new_accum) = accum.parent() {
-            accum = new_accum;
-        } else {
-            bail!("{} and {} share no common parent!", root.display(), target.display());
-        }
-
         if target.starts_with(accum) {
             let fragment = get_relative_fragment(accum, &target)?;
+            if super_frag.is_empty() {
+                return Ok(fragment.trim_start_matches('/').to_string());
+            }
             super_frag.pop();
             return Ok(format!("{super_frag}{fragment}"));
         }
 
+        if let Some(new_accum) = accum.parent() {
+            accum = new_accum;
+        } else {
+            bail!("{} and {} share no common parent!", root.display(), target.display());
+        }
         super_frag.push_str("../");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_path_to_child_directory() {
+        let dir = std::env::temp_dir().join(format!("mkwebfont-paths-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fonts")).unwrap();
+        std::fs::create_dir_all(dir.join("css")).unwrap();
+
+        let css = dir.join("fonts.css");
+        assert_eq!(get_relative_from(&css, &dir.join("fonts")).unwrap(), "fonts");
+        assert_eq!(get_relative_from(&css, &dir).unwrap(), "");
+        let css = dir.join("css/fonts.css");
+        assert_eq!(get_relative_from(&css, &dir.join("fonts")).unwrap(), "../fonts");
+        assert_eq!(get_relative_from(&css, &dir).unwrap(), "..");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
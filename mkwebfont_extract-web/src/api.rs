@@ -1,5 +1,5 @@
 use crate::{
-    gather_css::CssCache,
+    gather_css::{CssCache, GenericFamilies},
     rewrite_css::{RewriteContext, RewriteTargets},
     webroot::Webroot,
     webroot_info::TextInfoBuilder,
@@ -7,6 +7,7 @@ use crate::{
 };
 use anyhow::Result;
 use arcstr::ArcStr;
+use enumset::{EnumSet, EnumSetType};
 use mkwebfont_common::join_set::JoinSet;
 use std::{
     path::{Path, PathBuf},
@@ -15,6 +16,35 @@ use std::{
 use tokio::sync::RwLock;
 use tracing::{info, info_span, Instrument};
 
+/// Flags controlling how text is extracted from a webroot.
+#[derive(EnumSetType, Debug)]
+pub enum ExtractorFlags {
+    /// Assumes that every font stack used to display a pseudo-element's `content` may need to
+    /// display the `content` of any pseudo-element rule on the site, whether or not it matches.
+    ///
+    /// This is meant for icon fonts where the classes are added dynamically.
+    KeepAllContent,
+}
+
+/// Configuration for how text is extracted from a webroot.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractorConfig {
+    pub flags: EnumSet<ExtractorFlags>,
+    /// The concrete font families generic font families in the webroot's CSS are treated as.
+    pub generic_families: GenericFamilies,
+}
+impl ExtractorConfig {
+    pub fn new(flags: EnumSet<ExtractorFlags>) -> Self {
+        ExtractorConfig { flags, ..Default::default() }
+    }
+
+    /// Treats a generic font family (such as `system-ui`) as the given concrete font family.
+    pub fn generic_family(&mut self, generic: &str, family: &str) -> &mut Self {
+        self.generic_families.insert(generic, family);
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct WebrootInfoExtractor(Arc<WebrootInfoExtractorData>);
 #[derive(Debug)]
@@ -22,12 +52,26 @@ struct WebrootInfoExtractorData {
     builder: Arc<RwLock<TextInfoBuilder>>,
     target: Arc<RwLock<RewriteTargets>>,
     css_cache: CssCache,
+    config: ExtractorConfig,
 }
 impl WebrootInfoExtractor {
     pub fn new() -> Self {
         Default::default()
     }
 
+    pub fn with_flags(flags: EnumSet<ExtractorFlags>) -> Self {
+        Self::with_config(ExtractorConfig::new(flags))
+    }
+
+    pub fn with_config(config: ExtractorConfig) -> Self {
+        WebrootInfoExtractor(Arc::new(WebrootInfoExtractorData {
+            builder: Arc::new(RwLock::new(TextInfoBuilder::default())),
+            target: Arc::new(RwLock::new(RewriteTargets::default())),
+            css_cache: CssCache::new(config.generic_families.clone()),
+            config,
+        }))
+    }
+
     fn convert_inject_css(inject_css: &[&str]) -> Vec<ArcStr> {
         inject_css.iter().map(|x| ArcStr::from(*x)).collect()
     }
@@ -69,7 +113,7 @@ impl WebrootInfoExtractor {
             .builder
             .read()
             .await
-            .build(&(*self.0.target.read().await))
+            .build(&(*self.0.target.read().await), &self.0.config)
     }
 }
 impl WebrootInfoExtractorData {
@@ -110,17 +154,14 @@ impl WebrootInfoExtractorData {
 }
 impl Default for WebrootInfoExtractor {
     fn default() -> Self {
-        WebrootInfoExtractor(Arc::new(WebrootInfoExtractorData {
-            builder: Arc::new(RwLock::new(TextInfoBuilder::default())),
-            target: Arc::new(RwLock::new(RewriteTargets::default())),
-            css_cache: CssCache::new(),
-        }))
+        WebrootInfoExtractor::with_config(ExtractorConfig::default())
     }
 }
 
 impl WebrootInfo {
     pub async fn rewrite_webroot(&self, ctx: RewriteContext) -> Result<()> {
-        crate::rewrite_css::perform_rewrite(&self.targets, Arc::new(ctx)).await?;
+        crate::rewrite_css::perform_rewrite(&self.targets, &self.generic_families, Arc::new(ctx))
+            .await?;
         Ok(())
     }
 }
@@ -1,13 +1,16 @@
 use crate::{
-    gather_css::{parse_declarations, ParsedCssRule, RawCssRule, RawCssRuleDeclarations},
+    gather_css::{
+        parse_declarations, GenericFamilies, ParsedCssRule, RawCssRule, RawCssRuleDeclarations,
+    },
     utils::NodeId,
+    warnings,
 };
 use anyhow::Result;
 use arcstr::ArcStr;
 use kuchikiki::{traits::NodeIterator, NodeRef, Selectors};
 use lightningcss::{
     declaration::DeclarationBlock,
-    properties::font::{AbsoluteFontWeight, FontStyle},
+    properties::font::{AbsoluteFontWeight, FontStyle, FontWeight},
     stylesheet::ParserOptions,
 };
 use mkwebfont_common::hashing::{WyHashBuilder, WyHashSet};
@@ -64,7 +67,7 @@ impl<T> Default for NodeProperty<T> {
 #[derive(Debug, Default)]
 struct NodeProperties {
     font_stack: NodeProperty<Arc<[ArcStr]>>,
-    font_weight: NodeProperty<i32>,
+    font_weight: NodeProperty<ParsedFontWeight>,
     font_style: NodeProperty<ParsedFontStyle>,
     is_displayed: NodeProperty<bool>,
     content: NodeProperty<ArcStr>,
@@ -76,6 +79,34 @@ struct NodeInfo {
     pseudo_elements: HashMap<ArcStr, NodeProperties>,
 }
 
+/// A font weight, which may be relative to the weight inherited from the parent element.
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+enum ParsedFontWeight {
+    Absolute(i32),
+    Bolder,
+    Lighter,
+}
+
+/// Returns the weight used for `font-weight: bolder` given the inherited weight.
+fn bolder_weight(weight: i32) -> i32 {
+    match weight {
+        ..350 => 400,
+        350..550 => 700,
+        550..900 => 900,
+        _ => weight,
+    }
+}
+
+/// Returns the weight used for `font-weight: lighter` given the inherited weight.
+fn lighter_weight(weight: i32) -> i32 {
+    match weight {
+        ..100 => weight,
+        100..550 => 100,
+        550..750 => 400,
+        _ => 700,
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Copy, Clone)]
 pub enum ParsedFontStyle {
     Normal,
@@ -109,9 +140,13 @@ fn apply_properties(
         .push_node(&decls.font_stack, is_conditional);
     properties.font_weight.push_node(
         &decls.font_weight.map(|x| match x {
-            AbsoluteFontWeight::Weight(w) => *w as i32,
-            AbsoluteFontWeight::Normal => 400,
-            AbsoluteFontWeight::Bold => 700,
+            FontWeight::Absolute(AbsoluteFontWeight::Weight(w)) => {
+                ParsedFontWeight::Absolute(*w as i32)
+            }
+            FontWeight::Absolute(AbsoluteFontWeight::Normal) => ParsedFontWeight::Absolute(400),
+            FontWeight::Absolute(AbsoluteFontWeight::Bold) => ParsedFontWeight::Absolute(700),
+            FontWeight::Bolder => ParsedFontWeight::Bolder,
+            FontWeight::Lighter => ParsedFontWeight::Lighter,
         }),
         is_conditional,
     );
@@ -160,8 +195,32 @@ impl ResolvedNodeProperties {
             set.extend(props.active.iter().cloned());
         }
 
+        /// Relative weights are resolved against every weight inherited from the parent.
+        fn push_weights(set: &mut WyHashSet<i32>, props: &NodeProperty<ParsedFontWeight>) {
+            let mut inherited = match props.overwritten {
+                true => std::mem::take(set),
+                false => set.clone(),
+            };
+            if inherited.is_empty() {
+                inherited.insert(400);
+            }
+            for weight in &props.active {
+                match weight {
+                    ParsedFontWeight::Absolute(w) => {
+                        set.insert(*w);
+                    }
+                    ParsedFontWeight::Bolder => {
+                        set.extend(inherited.iter().map(|x| bolder_weight(*x)));
+                    }
+                    ParsedFontWeight::Lighter => {
+                        set.extend(inherited.iter().map(|x| lighter_weight(*x)));
+                    }
+                }
+            }
+        }
+
         push_property(&mut self.font_stack, &props.font_stack);
-        push_property(&mut self.font_weight, &props.font_weight);
+        push_weights(&mut self.font_weight, &props.font_weight);
         push_property(&mut self.font_style, &props.font_style);
         // note: content isn't inherited
     }
@@ -174,34 +233,49 @@ pub struct ResolvedNode {
 }
 
 impl RawNodeInfo {
-    pub fn compute(document: &NodeRef, rules: &[Arc<RawCssRule>]) -> Result<Self> {
+    pub fn compute(
+        document: &NodeRef,
+        rules: &[Arc<RawCssRule>],
+        generics: &GenericFamilies,
+    ) -> Result<Self> {
         static SELECTOR: LazyLock<Selectors> =
             LazyLock::new(|| Selectors::compile("*[style]").unwrap());
 
-        let mut info = Self::default();
-        for rule in rules {
-            apply_rule_to_raw_info(&mut info, document, &rule);
-        }
+        let mut inline_styles = Vec::new();
         for elem in SELECTOR.filter(document.inclusive_descendants().elements()) {
             let style = elem.attributes.borrow();
             let style = style.get("style").unwrap();
             match DeclarationBlock::parse_string(style, ParserOptions::default()) {
                 Ok(block) => {
-                    if let Some(decls) = parse_declarations(&block)? {
-                        apply_properties(
-                            &mut info
-                                .raw
-                                .entry(NodeId::from_node(elem.as_node()))
-                                .or_default()
-                                .properties,
-                            false,
-                            &decls,
-                        );
-                    }
+                    let normal = parse_declarations(&block, false, generics)?;
+                    let important = parse_declarations(&block, true, generics)?;
+                    inline_styles.push((NodeId::from_node(elem.as_node()), normal, important));
                 }
-                Err(e) => warn!("Error parsing style {style:?}: {e}"),
+                Err(e) => warn!(target: warnings::UNPARSED, "Error parsing style {style:?}: {e}"),
             };
         }
+
+        // inline styles take precedence over the stylesheet declarations of the same importance
+        let mut info = Self::default();
+        let important_start = rules.partition_point(|x| !x.important);
+        for rule in &rules[..important_start] {
+            apply_rule_to_raw_info(&mut info, document, rule);
+        }
+        for (node, decls, _) in &inline_styles {
+            if let Some(decls) = decls {
+                let elem = info.raw.entry(*node).or_default();
+                apply_properties(&mut elem.properties, false, decls);
+            }
+        }
+        for rule in &rules[important_start..] {
+            apply_rule_to_raw_info(&mut info, document, rule);
+        }
+        for (node, _, decls) in &inline_styles {
+            if let Some(decls) = decls {
+                let elem = info.raw.entry(*node).or_default();
+                apply_properties(&mut elem.properties, false, decls);
+            }
+        }
         Ok(info)
     }
 
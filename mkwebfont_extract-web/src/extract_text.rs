@@ -18,14 +18,14 @@ pub async fn extract_text(
     inject_css: &[ArcStr],
     builder: Arc<RwLock<TextInfoBuilder>>,
 ) -> Result<WyHashSet<Arc<[ArcStr]>>> {
-    let rules = css_cache
+    let (rules, font_faces) = css_cache
         .get_rules_from_document(&data, root, inject_css)
         .await?;
 
     let mut samples = Vec::new();
     {
         let document = parse_html().one(data.as_str());
-        let node_info = RawNodeInfo::compute(&document, &rules)?;
+        let node_info = RawNodeInfo::compute(&document, &rules, css_cache.generic_families())?;
 
         fn push_samples(
             samples: &mut Vec<(ResolvedNodeProperties, Vec<ArcStr>)>,
@@ -132,6 +132,8 @@ pub async fn extract_text(
     }
 
     let mut lock = builder.write().await;
+    lock.push_content_rules(&rules);
+    lock.push_font_faces(&font_faces);
     let mut stacks = WyHashSet::default();
     for (props, sample) in samples {
         stacks.extend(lock.push_sample(&props, &sample));
@@ -1,4 +1,4 @@
-use crate::{utils, webroot::RelaWebroot};
+use crate::{utils, warnings, webroot::RelaWebroot};
 use anyhow::Result;
 use arcstr::ArcStr;
 use kuchikiki::{iter::NodeIterator, parse_html, traits::TendrilSink, Selectors};
@@ -26,7 +26,9 @@ fn extract_css_sources(document: &ArcStr) -> Result<Vec<CssSource>> {
         match tag.name.local.as_bytes() {
             b"link" => match tag.attributes.borrow().get("href") {
                 Some(x) => data.push(CssSource::RelFile(x.to_string())),
-                None => warn!("Tag does not contain href: {tag:?}"),
+                None => {
+                    warn!(target: warnings::MISSING_RESOURCE, "Tag does not contain href: {tag:?}")
+                }
             },
             b"style" => data.push(CssSource::Embedded(utils::inner_html(tag.as_node()).into())),
             _ => unreachable!(),
@@ -49,7 +51,9 @@ async fn gather_all_css(
         match source {
             CssSource::RelFile(path) => match root.load_rela(&path).await {
                 Ok(data) => result.push(data),
-                Err(e) => warn!("Could not load '{path}': {e:?}"),
+                Err(e) => {
+                    warn!(target: warnings::MISSING_RESOURCE, "Could not load '{path}': {e:?}")
+                }
             },
             CssSource::Embedded(tag) => result.push((tag.into(), root.clone())),
         }
@@ -57,28 +61,106 @@ async fn gather_all_css(
     Ok(result)
 }
 
+/// Sorts the layers declared in the stylesheets of a document by their precedence, from lowest to
+/// highest.
+///
+/// Layers are ordered by the order they were first declared in, except that each layer comes
+/// after all of its sublayers, as the rules of a layer that are not in any sublayer behave like an
+/// implicit last sublayer.
+fn layer_cascade_order(layers: &[ArcStr]) -> Vec<ArcStr> {
+    fn parent_of<'a>(layers: &'a [ArcStr], layer: &ArcStr) -> Option<&'a ArcStr> {
+        layers
+            .iter()
+            .filter(|x| {
+                layer.len() > x.len()
+                    && layer.starts_with(x.as_str())
+                    && layer.as_bytes()[x.len()] == b'.'
+            })
+            .max_by_key(|x| x.len())
+    }
+    fn visit(layers: &[ArcStr], parent: Option<&ArcStr>, out: &mut Vec<ArcStr>) {
+        for layer in layers {
+            if parent_of(layers, layer) == parent {
+                visit(layers, Some(layer), out);
+                out.push(layer.clone());
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    visit(layers, None, &mut out);
+    out
+}
+
 async fn process_rules(
     sources: &[(ArcStr, RelaWebroot)],
     css_cache: &CssCache,
-) -> Result<Vec<Arc<RawCssRule>>> {
+) -> Result<(Vec<Arc<RawCssRule>>, Vec<FontFaceRange>)> {
     let mut rules: Vec<Arc<RawCssRule>> = Vec::new();
+    let mut layers: Vec<ArcStr> = Vec::new();
+    let mut font_faces = Vec::new();
     for (source, new_root) in sources {
-        for rule in &*css_cache.get_css(source.clone(), new_root).await? {
-            rules.push(rule.clone());
+        let parsed = css_cache.get_css(source.clone(), new_root).await?;
+        for layer in &parsed.layers {
+            if !layers.contains(layer) {
+                layers.push(layer.clone());
+            }
         }
+        rules.extend(parsed.rules.iter().cloned());
+        font_faces.extend(parsed.font_faces.iter().cloned());
     }
-    rules.sort_by_key(|x| x.specificity);
-    Ok(rules)
+
+    // Rules in later layers take precedence over rules in earlier layers regardless of
+    // specificity, and rules outside any layer take precedence over all layered rules. Within a
+    // layer, its own rules take precedence over the rules in its sublayers.
+    //
+    // `!important` declarations take precedence over all normal declarations, and the order of
+    // layers is reversed for them.
+    let layers = layer_cascade_order(&layers);
+    let layer_rank = |rule: &RawCssRule| {
+        let position = rule
+            .layer
+            .as_ref()
+            .map(|layer| layers.iter().position(|x| x == layer).unwrap_or(0));
+        match (rule.important, position) {
+            (false, Some(position)) => position,
+            (false, None) => layers.len(),
+            (true, Some(position)) => layers.len() - position,
+            (true, None) => 0,
+        }
+    };
+    rules.sort_by_key(|x| (x.important, layer_rank(x), x.specificity));
+    Ok((rules, font_faces))
 }
 
 impl CssCache {
+    /// Returns the CSS rules that apply to a document in cascade order, along with the coverage
+    /// declared by the `@font-face` rules in its stylesheets.
     pub async fn get_rules_from_document(
         &self,
         document: &ArcStr,
         root: &RelaWebroot,
         inject: &[ArcStr],
-    ) -> Result<Vec<Arc<RawCssRule>>> {
+    ) -> Result<(Vec<Arc<RawCssRule>>, Vec<FontFaceRange>)> {
         let sources = gather_all_css(document, root, inject).await?;
         process_rules(&sources, self).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_follow_their_sublayers() {
+        let layers: Vec<ArcStr> = ["a", "b", "a.x", "a.x.y", "a.z", "b.x"]
+            .into_iter()
+            .map(ArcStr::from)
+            .collect();
+        let order: Vec<_> = layer_cascade_order(&layers)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(order, ["a.x.y", "a.x", "a.z", "a", "b.x", "b"]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{consts::CACHE_SIZE, webroot::RelaWebroot};
+use crate::{consts::CACHE_SIZE, warnings, webroot::RelaWebroot};
 use anyhow::{bail, ensure, Error, Result};
 use arcstr::ArcStr;
 use async_recursion::async_recursion;
@@ -13,14 +13,19 @@ use lightningcss::{
         font::{AbsoluteFontWeight, FontFamily, FontStyle, FontWeight, GenericFontFamily},
         Property, PropertyId,
     },
-    rules::{style::StyleRule, CssRule, CssRuleList},
+    rules::{
+        font_face::{FontFaceProperty, FontFaceRule},
+        layer::LayerName,
+        style::StyleRule,
+        CssRule, CssRuleList,
+    },
     selector::Component,
     stylesheet::{ParserOptions, StyleSheet},
     traits::ToCss,
 };
-use mkwebfont_common::hashing::WyHashBuilder;
+use mkwebfont_common::{character_set::CharacterSet, hashing::WyHashBuilder};
 use moka::future::{Cache, CacheBuilder};
-use std::{borrow::Cow, path::Path, sync::Arc};
+use std::{borrow::Cow, collections::BTreeMap, path::Path, sync::Arc};
 use tracing::{info, info_span, warn, Instrument};
 
 #[derive(Clone, Debug)]
@@ -30,6 +35,50 @@ pub struct RawCssRule {
     pub pseudo_element: Option<ArcStr>,
     pub declarations: Arc<RawCssRuleDeclarations>,
     pub specificity: u32,
+    /// The full name of the cascade layer this rule is in, if any.
+    pub layer: Option<ArcStr>,
+    /// Whether this rule holds the `!important` declarations of a style rule.
+    pub important: bool,
+}
+
+/// The rules parsed from a stylesheet.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedCss {
+    pub rules: Vec<Arc<RawCssRule>>,
+    /// The full names of all cascade layers in the stylesheet, in the order they were declared.
+    pub layers: Vec<ArcStr>,
+    /// The coverage declared by the `@font-face` rules in the stylesheet.
+    pub font_faces: Vec<FontFaceRange>,
+}
+
+/// The characters an author's `@font-face` rule declares it covers.
+#[derive(Clone, Debug)]
+pub struct FontFaceRange {
+    pub family: ArcStr,
+    /// The characters in the `unicode-range` of the rule, or `None` if it has no `unicode-range`
+    /// and so covers every character.
+    pub range: Option<CharacterSet>,
+}
+
+fn parse_font_face_range(rule: &FontFaceRule) -> Option<FontFaceRange> {
+    let mut family = None;
+    let mut range = None;
+    for property in &rule.properties {
+        match property {
+            FontFaceProperty::FontFamily(FontFamily::FamilyName(name)) => {
+                family = Some(ArcStr::from(name.to_lowercase()));
+            }
+            FontFaceProperty::UnicodeRange(ranges) => {
+                let mut chars = CharacterSet::new();
+                for unicode_range in ranges {
+                    chars.extend(unicode_range.start..=unicode_range.end);
+                }
+                range = Some(chars);
+            }
+            _ => {}
+        }
+    }
+    Some(FontFaceRange { family: family?, range })
 }
 
 #[derive(Clone, Debug)]
@@ -55,26 +104,67 @@ impl<T> ParsedCssRule<T> {
 #[derive(Clone, Debug)]
 pub struct RawCssRuleDeclarations {
     pub font_stack: ParsedCssRule<Arc<[ArcStr]>>,
-    pub font_weight: ParsedCssRule<AbsoluteFontWeight>,
+    pub font_weight: ParsedCssRule<FontWeight>,
     pub font_style: ParsedCssRule<FontStyle>,
     pub is_displayed: ParsedCssRule<bool>,
     pub content: ParsedCssRule<ArcStr>,
 }
 
+/// The system font keywords allowed in the `font` shorthand.
+const SYSTEM_FONTS: &[&str] =
+    &["caption", "icon", "menu", "message-box", "small-caption", "status-bar"];
+
+/// Concrete font families that generic font families (such as `system-ui`) are treated as when
+/// parsing CSS. Generic families without a mapping are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenericFamilies(BTreeMap<String, ArcStr>);
+impl GenericFamilies {
+    /// Treats a generic font family (such as `system-ui` or `ui-sans-serif`) as the given concrete
+    /// font family, rather than ignoring it.
+    ///
+    /// This allows text styled only with a generic family to be subset for the font that generic
+    /// family is expected to resolve to.
+    pub fn insert(&mut self, generic: &str, family: &str) {
+        self.0
+            .insert(generic.to_lowercase(), family.to_lowercase().into());
+    }
+
+    fn get(&self, generic: &str) -> Option<&ArcStr> {
+        self.0.get(generic)
+    }
+
+    /// Returns the mappings, sorted by generic family name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ArcStr)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
 /// Parses CSS font families into the form used in the rest of this subcrate.
-pub fn parse_font_families(families: &[FontFamily<'_>]) -> ParsedCssRule<Arc<[ArcStr]>> {
+pub fn parse_font_families(
+    families: &[FontFamily<'_>],
+    generics: &GenericFamilies,
+) -> ParsedCssRule<Arc<[ArcStr]>> {
     let mut new = Vec::new();
     for family in families {
         match family {
             FontFamily::Generic(GenericFontFamily::Inherit) => return ParsedCssRule::Inherit,
-            FontFamily::Generic(_) => {
-                warn!("Generic font families are ignored: {family:?}")
+            FontFamily::Generic(generic) => {
+                let name = generic
+                    .to_css_string(PrinterOptions::default())
+                    .unwrap_or_default();
+                match generics.get(&name) {
+                    Some(mapped) => new.push(mapped.clone()),
+                    None => warn!(
+                        target: warnings::GENERIC_FAMILY,
+                        "Generic font families are ignored: {family:?}"
+                    ),
+                }
             }
             FontFamily::FamilyName(name) => new.push(name.to_lowercase().into()),
         }
     }
     if new.is_empty() {
-        warn!("Found empty fonts list (excluding generics)");
+        warn!(target: warnings::GENERIC_FAMILY, "Found empty fonts list (excluding generics)");
         ParsedCssRule::IgnoreSet
     } else {
         ParsedCssRule::Override(new.into())
@@ -82,7 +172,14 @@ pub fn parse_font_families(families: &[FontFamily<'_>]) -> ParsedCssRule<Arc<[Ar
 }
 
 /// Parses the list of declarations in a CSS rule into only the ones we need.
-pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleDeclarations>> {
+///
+/// If `important` is set, only the `!important` declarations are parsed, and otherwise, only the
+/// normal declarations are parsed, as the two take part in the cascade separately.
+pub fn parse_declarations(
+    style: &DeclarationBlock,
+    important: bool,
+    generics: &GenericFamilies,
+) -> Result<Option<RawCssRuleDeclarations>> {
     let mut raw_declarations = RawCssRuleDeclarations {
         font_stack: ParsedCssRule::NoneSet,
         font_weight: ParsedCssRule::NoneSet,
@@ -92,26 +189,11 @@ pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleD
     };
     let mut is_interesting = false;
 
-    if !style.important_declarations.is_empty() {
-        warn!("`!important` is not handled correctly.");
-    }
-
-    for declaration in style
-        .important_declarations
-        .iter()
-        .chain(style.declarations.iter())
-    {
-        /// Parses CSS font weight declarations.
-        fn parse_font_weight(weight: &FontWeight) -> ParsedCssRule<AbsoluteFontWeight> {
-            match weight {
-                FontWeight::Absolute(v) => ParsedCssRule::Override(v.clone()),
-                FontWeight::Bolder | FontWeight::Lighter => {
-                    warn!("Relative font weights are not supported.");
-                    ParsedCssRule::NoneSet
-                }
-            }
-        }
-
+    let declarations = match important {
+        true => &style.important_declarations,
+        false => &style.declarations,
+    };
+    for declaration in declarations {
         match declaration {
             Property::Display(kind) => {
                 if let Display::Keyword(DisplayKeyword::None) = kind {
@@ -123,17 +205,17 @@ pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleD
             }
 
             Property::Font(font) => {
-                raw_declarations.font_stack = parse_font_families(&font.family);
-                raw_declarations.font_weight = parse_font_weight(&font.weight);
+                raw_declarations.font_stack = parse_font_families(&font.family, generics);
+                raw_declarations.font_weight = ParsedCssRule::Override(font.weight.clone());
                 raw_declarations.font_style = ParsedCssRule::Override(font.style.clone());
                 is_interesting = true;
             }
             Property::FontFamily(family) => {
-                raw_declarations.font_stack = parse_font_families(&family);
+                raw_declarations.font_stack = parse_font_families(&family, generics);
                 is_interesting = true;
             }
             Property::FontWeight(weight) => {
-                raw_declarations.font_weight = parse_font_weight(weight);
+                raw_declarations.font_weight = ParsedCssRule::Override(weight.clone());
                 is_interesting = true;
             }
             Property::FontStyle(style) => {
@@ -175,27 +257,54 @@ pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleD
                     _ => {}
                 }
             }
+            // System font keywords in the `font` shorthand (e.g. `font: menu`) select a font we
+            // cannot generate webfonts for, so they are treated like a generic font family.
+            Property::Unparsed(UnparsedProperty { property_id: PropertyId::Font, value })
+                if value.0.len() == 1
+                    && match &value.0[0] {
+                        TokenOrValue::Token(Token::Ident(id)) => {
+                            SYSTEM_FONTS.iter().any(|x| id.eq_ignore_ascii_case(x))
+                        }
+                        _ => false,
+                    } =>
+            {
+                raw_declarations.font_stack = ParsedCssRule::IgnoreSet;
+                raw_declarations.font_weight =
+                    ParsedCssRule::Override(FontWeight::Absolute(AbsoluteFontWeight::Normal));
+                raw_declarations.font_style = ParsedCssRule::Override(FontStyle::Normal);
+                is_interesting = true;
+            }
             Property::Unparsed(UnparsedProperty { property_id, value }) => match property_id {
-                PropertyId::Display => warn!("Unparsed display property: {value:?}"),
-                PropertyId::Font => warn!("Unparsed font property: {value:?}"),
-                PropertyId::FontFamily => warn!("Unparsed font-family property: {value:?}"),
-                PropertyId::FontWeight => warn!("Unparsed font-weight property: {value:?}"),
-                PropertyId::FontStyle => warn!("Unparsed font-style property: {value:?}"),
+                PropertyId::Display => {
+                    warn!(target: warnings::UNPARSED, "Unparsed display property: {value:?}")
+                }
+                PropertyId::Font => {
+                    warn!(target: warnings::UNPARSED, "Unparsed font property: {value:?}")
+                }
+                PropertyId::FontFamily => {
+                    warn!(target: warnings::UNPARSED, "Unparsed font-family property: {value:?}")
+                }
+                PropertyId::FontWeight => {
+                    warn!(target: warnings::UNPARSED, "Unparsed font-weight property: {value:?}")
+                }
+                PropertyId::FontStyle => {
+                    warn!(target: warnings::UNPARSED, "Unparsed font-style property: {value:?}")
+                }
                 _ => {}
             },
             Property::Custom(CustomProperty { name: CustomPropertyName::Unknown(name), value }) => {
                 match name.0.as_ref() {
                     "font" => {
-                        warn!("Unparsed font");
+                        warn!(target: warnings::UNPARSED, "Unparsed font");
                     }
                     "font-family" => {
-                        warn!("Unparsed font-family");
+                        warn!(target: warnings::UNPARSED, "Unparsed font-family");
                     }
                     "font-weight" => {
-                        warn!("Unparsed font-weight");
+                        warn!(target: warnings::UNPARSED, "Unparsed font-weight");
                     }
                     "font-style" => {
-                        warn!("Unparsed font-style");
+                        warn!(target: warnings::UNPARSED, "Unparsed font-style");
                     }
                     "content" => {
                         if value.0.len() == 1 {
@@ -209,10 +318,12 @@ pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleD
                                     raw_declarations.content = ParsedCssRule::OverrideUnset;
                                     is_interesting = true;
                                 }
-                                _ => warn!("Could not parse `content` attribute: {value:?}"),
+                                _ => {
+                                    warn!(target: warnings::UNPARSED, "Could not parse `content` attribute: {value:?}")
+                                }
                             }
                         } else {
-                            warn!("Could not parse `content` attribute: {value:?}");
+                            warn!(target: warnings::UNPARSED, "Could not parse `content` attribute: {value:?}");
                         }
                     }
                     // TODO: Support stylistic sets and font variation settings.
@@ -233,11 +344,7 @@ pub fn parse_declarations(style: &DeclarationBlock) -> Result<Option<RawCssRuleD
 }
 
 /// Parses CSS data into a list of CSS rules.
-async fn parse_css(
-    data: &str,
-    root: &RelaWebroot,
-    cache: &CssCache,
-) -> Result<Vec<Arc<RawCssRule>>> {
+async fn parse_css(data: &str, root: &RelaWebroot, cache: &CssCache) -> Result<ParsedCss> {
     /// The result of filtering a selector.
     #[derive(Debug)]
     struct FilteredSelector<'a> {
@@ -351,8 +458,14 @@ async fn parse_css(
         out: &mut Vec<Arc<RawCssRule>>,
         style: &StyleRule,
         force_conditional: bool,
+        layer: Option<&ArcStr>,
+        generics: &GenericFamilies,
     ) -> Result<()> {
-        if let Some(declarations) = parse_declarations(&style.declarations)? {
+        for important in [false, true] {
+            let Some(declarations) = parse_declarations(&style.declarations, important, generics)?
+            else {
+                continue;
+            };
             let declarations = Arc::new(declarations);
             for selector in &style.selectors.0 {
                 let filtered = filter_selector(selector, selector)?;
@@ -372,6 +485,8 @@ async fn parse_css(
                     pseudo_element: filtered.pseudo_element.map(Into::into),
                     declarations: declarations.clone(),
                     specificity: filtered.specificity,
+                    layer: layer.cloned(),
+                    important,
                 };
                 out.push(Arc::new(raw));
             }
@@ -379,20 +494,37 @@ async fn parse_css(
         Ok(())
     }
 
-    /// Applies the `force_conditional` flag to
-    fn apply_force_conditional(
+    /// Returns the full name of a layer in an imported stylesheet, when it is imported into the
+    /// given layer.
+    fn imported_layer_name(
+        import_layer: Option<&ArcStr>,
+        layer: Option<&ArcStr>,
+    ) -> Option<ArcStr> {
+        match (import_layer, layer) {
+            (Some(import_layer), Some(layer)) => Some(format!("{import_layer}.{layer}").into()),
+            (Some(import_layer), None) => Some(import_layer.clone()),
+            (None, layer) => layer.cloned(),
+        }
+    }
+
+    /// Adds the rules of an imported stylesheet, applying the `force_conditional` flag to them
+    /// and placing them in the layer they are imported into.
+    fn push_imported_rules(
         out: &mut Vec<Arc<RawCssRule>>,
         orig_list: &[Arc<RawCssRule>],
         force_conditional: bool,
+        import_layer: Option<&ArcStr>,
     ) {
         for rule in orig_list {
-            if !rule.is_conditional && force_conditional {
+            if (!rule.is_conditional && force_conditional) || import_layer.is_some() {
                 out.push(Arc::new(RawCssRule {
                     selector: rule.selector.clone(),
-                    is_conditional: true,
+                    is_conditional: rule.is_conditional || force_conditional,
                     pseudo_element: rule.pseudo_element.clone(),
                     declarations: rule.declarations.clone(),
                     specificity: rule.specificity,
+                    layer: imported_layer_name(import_layer, rule.layer.as_ref()),
+                    important: rule.important,
                 }));
             } else {
                 out.push(rule.clone());
@@ -400,22 +532,62 @@ async fn parse_css(
         }
     }
 
+    /// Declares a cascade layer along with any of its parent layers not yet declared, returning
+    /// its full name.
+    fn declare_layer(
+        out: &mut ParsedCss,
+        parent: Option<&ArcStr>,
+        name: Option<&LayerName>,
+        root: &RelaWebroot,
+    ) -> ArcStr {
+        let parts = match name {
+            Some(name) => name.0.iter().map(|x| x.to_string()).collect(),
+            // anonymous layers can never be referred to again, so they are given a unique name
+            None => {
+                vec![format!("<anonymous {} in {}>", out.layers.len(), root.file_name().display())]
+            }
+        };
+        let mut name = parent.cloned();
+        for part in parts {
+            let full_name: ArcStr = match &name {
+                Some(parent) => format!("{parent}.{part}").into(),
+                None => part.into(),
+            };
+            if !out.layers.contains(&full_name) {
+                out.layers.push(full_name.clone());
+            }
+            name = Some(full_name);
+        }
+        name.unwrap()
+    }
+
     /// The main recursive function that handles parsing rules.
     ///
-    /// This is recursive to allow for handling media queries and import statements.
+    /// This is recursive to allow for handling media queries, layers and import statements.
     #[async_recursion]
     async fn push_rules(
-        out: &mut Vec<Arc<RawCssRule>>,
+        out: &mut ParsedCss,
         rules: &CssRuleList<'_>,
         root: &RelaWebroot,
         force_conditional: bool,
+        layer: Option<&'async_recursion ArcStr>,
         cache: &CssCache,
     ) -> Result<()> {
         for rule in &rules.0 {
             match rule {
                 CssRule::Media(media_query) => {
                     let is_conditional = force_conditional || !media_query.query.always_matches();
-                    push_rules(out, &media_query.rules, root, is_conditional, cache).await?
+                    push_rules(out, &media_query.rules, root, is_conditional, layer, cache).await?
+                }
+                CssRule::LayerBlock(block) => {
+                    let name = declare_layer(out, layer, block.name.as_ref(), root);
+                    push_rules(out, &block.rules, root, force_conditional, Some(&name), cache)
+                        .await?
+                }
+                CssRule::LayerStatement(statement) => {
+                    for name in &statement.names {
+                        declare_layer(out, layer, Some(name), root);
+                    }
                 }
                 // @import is *not* cached for ease of coding.
                 //
@@ -423,51 +595,79 @@ async fn parse_css(
                 // should be fairly rare with the kind of static sites extract-web is meant for.
                 CssRule::Import(import_statement) => {
                     let url: &str = &import_statement.url;
+                    let import_layer = import_statement
+                        .layer
+                        .as_ref()
+                        .map(|name| declare_layer(out, layer, name.as_ref(), root));
                     match root.load_rela(url).await {
                         Ok((data, new_root)) => {
                             let parsed = cache.get_css(data, &new_root).await?;
-                            apply_force_conditional(out, &parsed, force_conditional);
+                            for layer in &parsed.layers {
+                                let layer = imported_layer_name(import_layer.as_ref(), Some(layer))
+                                    .unwrap();
+                                if !out.layers.contains(&layer) {
+                                    out.layers.push(layer);
+                                }
+                            }
+                            push_imported_rules(
+                                &mut out.rules,
+                                &parsed.rules,
+                                force_conditional,
+                                import_layer.as_ref(),
+                            );
+                            out.font_faces.extend(parsed.font_faces.iter().cloned());
+                        }
+                        Err(e) => {
+                            warn!(target: warnings::MISSING_RESOURCE, "Could not load '{url}': {e}")
                         }
-                        Err(e) => warn!("Could not load '{url}': {e}"),
                     }
                 }
                 CssRule::Style(style) => {
                     if !style.rules.0.is_empty() {
-                        warn!("Nested CSS rules are not supported!!");
+                        warn!(target: warnings::UNSUPPORTED, "Nested CSS rules are not supported!!");
                     }
-                    if let Err(e) = generate_rules(out, style, force_conditional) {
-                        warn!("Rules ignored: {e}");
+                    let generics = &cache.generic_families;
+                    if let Err(e) =
+                        generate_rules(&mut out.rules, style, force_conditional, layer, generics)
+                    {
+                        warn!(target: warnings::UNSUPPORTED, "Rules ignored: {e}");
                     }
                 }
-                CssRule::FontFace(_) => warn!("Preexisting @font-face exists."),
-                css => warn!("CSS rule not recognized: {css:?}"),
+                CssRule::FontFace(rule) => {
+                    warn!(target: warnings::UNSUPPORTED, "Preexisting @font-face exists.");
+                    out.font_faces.extend(parse_font_face_range(rule));
+                }
+                css => warn!(target: warnings::UNSUPPORTED, "CSS rule not recognized: {css:?}"),
             }
         }
         Ok(())
     }
 
-    let mut rules = Vec::new();
+    let mut out = ParsedCss::default();
     let parsed = StyleSheet::parse(data, ParserOptions::default()).map_err(|x| x.into_owned())?;
-    push_rules(&mut rules, &parsed.rules, root, false, cache).await?;
-    Ok(rules)
+    push_rules(&mut out, &parsed.rules, root, false, None, cache).await?;
+    Ok(out)
 }
 
 #[derive(Debug, Clone)]
 pub struct CssCache {
-    cache: Arc<Cache<(ArcStr, Arc<Path>), Arc<[Arc<RawCssRule>]>, WyHashBuilder>>,
+    cache: Arc<Cache<(ArcStr, Arc<Path>), Arc<ParsedCss>, WyHashBuilder>>,
+    generic_families: Arc<GenericFamilies>,
 }
 impl CssCache {
-    pub fn new() -> Self {
+    pub fn new(generic_families: GenericFamilies) -> Self {
         CssCache {
             cache: Arc::new(CacheBuilder::new(CACHE_SIZE).build_with_hasher(Default::default())),
+            generic_families: Arc::new(generic_families),
         }
     }
 
-    pub async fn get_css(
-        &self,
-        source: ArcStr,
-        root: &RelaWebroot,
-    ) -> Result<Arc<[Arc<RawCssRule>]>> {
+    /// Returns the generic family mappings the stylesheets in this cache are parsed with.
+    pub fn generic_families(&self) -> &GenericFamilies {
+        &self.generic_families
+    }
+
+    pub async fn get_css(&self, source: ArcStr, root: &RelaWebroot) -> Result<Arc<ParsedCss>> {
         let root_name: Cow<str> = match root.file_name().file_name() {
             None => Cow::Borrowed("<unknown>"),
             Some(name) => name.to_string_lossy(),
@@ -494,3 +694,40 @@ impl CssCache {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(css: &str) -> RawCssRuleDeclarations {
+        let block = DeclarationBlock::parse_string(css, ParserOptions::default()).unwrap();
+        parse_declarations(&block, false, &GenericFamilies::default())
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn system_font_is_ignored() {
+        let decls = parse("font: menu");
+        assert!(matches!(decls.font_stack, ParsedCssRule::IgnoreSet));
+        assert!(matches!(
+            decls.font_weight,
+            ParsedCssRule::Override(FontWeight::Absolute(AbsoluteFontWeight::Normal))
+        ));
+        assert!(matches!(decls.font_style, ParsedCssRule::Override(FontStyle::Normal)));
+    }
+
+    #[test]
+    fn shorthand_with_line_height_parses_family() {
+        let decls = parse("font: italic bold 12px/1.5 Roboto, sans-serif");
+        match decls.font_stack {
+            ParsedCssRule::Override(stack) => assert_eq!(&*stack, &[ArcStr::from("roboto")]),
+            _ => panic!("font stack was not parsed: {:?}", decls.font_stack),
+        }
+        assert!(matches!(
+            decls.font_weight,
+            ParsedCssRule::Override(FontWeight::Absolute(AbsoluteFontWeight::Bold))
+        ));
+        assert!(matches!(decls.font_style, ParsedCssRule::Override(FontStyle::Italic)));
+    }
+}
@@ -4,7 +4,9 @@ mod extract_text;
 mod gather_css;
 mod rewrite_css;
 mod utils;
+pub mod warnings;
 mod webroot;
+mod webroot_cache;
 mod webroot_info;
 
 mod consts {
@@ -12,5 +14,6 @@ mod consts {
 }
 
 pub use api::*;
-pub use rewrite_css::RewriteContext;
+pub use gather_css::GenericFamilies;
+pub use rewrite_css::{validate_media_query, FontDisplay, RenderWebfontCss, RewriteContext};
 pub use webroot_info::{FontStackInfo, TextSample, WebrootInfo};
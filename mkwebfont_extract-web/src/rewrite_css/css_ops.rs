@@ -1,15 +1,19 @@
 use crate::{
-    gather_css::{parse_font_families, ParsedCssRule},
+    gather_css::{parse_font_families, GenericFamilies, ParsedCssRule},
     webroot::RelaWebroot,
     RewriteContext,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use arcstr::ArcStr;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use kuchikiki::{parse_html, traits::TendrilSink, NodeRef};
 use lightningcss::{
     declaration::DeclarationBlock,
+    media_query::MediaList,
     printer::PrinterOptions,
     properties::{
-        font::{AbsoluteFontWeight, FontFamily, FontWeight as CssFontWeight},
+        custom::{CustomProperty, CustomPropertyName, Token, TokenList, TokenOrValue},
+        font::{AbsoluteFontWeight, FontFamily, FontStretch, FontWeight as CssFontWeight},
         Property,
     },
     rules::{
@@ -17,19 +21,23 @@ use lightningcss::{
             FontFaceProperty, FontFaceRule, FontFormat, FontStyle as CssFontStyle, Source,
             UnicodeRange, UrlSource,
         },
+        media::MediaRule,
         CssRule, CssRuleList, Location,
     },
     stylesheet::{ParserOptions, StyleSheet},
-    traits::{ToCss, Zero},
-    values::{angle::Angle, size::Size2D, url::Url},
+    traits::{IntoOwned, ToCss, Zero},
+    values::{angle::Angle, percentage::Percentage, size::Size2D, string::CowArcStr, url::Url},
 };
 use mkwebfont_common::{
-    hashing::WyHashSet,
+    hashing::{wyhash, WyHashSet},
     paths::{get_relative_from, is_superpath},
 };
-use mkwebfont_fontops::font_info::FontStyle;
+use mkwebfont_fontops::{
+    font_info::FontStyle,
+    subsetter::{SubsetInfo, WebfontInfo},
+};
 use std::{borrow::Cow, sync::Arc};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 const DEFAULT_LOC: Location = Location { source_index: 0, line: 0, column: 0 };
 const DEFAULT_LOC_CSS: lightningcss::dependencies::Location =
@@ -41,18 +49,140 @@ fn printer() -> PrinterOptions<'static> {
     options
 }
 
+fn percentage_property(name: &'static str, value: f32) -> FontFaceProperty<'static> {
+    FontFaceProperty::Custom(CustomProperty {
+        name: CustomPropertyName::from(CowArcStr::from(name)),
+        value: TokenList(vec![TokenOrValue::Token(Token::Percentage {
+            has_sign: false,
+            unit_value: (value * 100.0).round() / 10000.0,
+            int_value: None,
+        })]),
+    })
+}
+
+fn ident_property(name: &'static str, value: &'static str) -> FontFaceProperty<'static> {
+    FontFaceProperty::Custom(CustomProperty {
+        name: CustomPropertyName::from(CowArcStr::from(name)),
+        value: TokenList(vec![TokenOrValue::Token(Token::Ident(CowArcStr::from(value)))]),
+    })
+}
+
+/// Returns the URL of a file in the store, choosing between store URIs by the file name.
+fn store_url(store_uris: &[Cow<str>], file_name: &str) -> String {
+    let store_uri = match store_uris {
+        [] => "",
+        [uri] => uri,
+        _ => &store_uris[(wyhash(0, file_name) % store_uris.len() as u64) as usize],
+    };
+    if store_uri.is_empty() {
+        file_name.to_string()
+    } else {
+        format!("{store_uri}/{file_name}")
+    }
+}
+
+/// Parses a media query list, such as `(min-width: 800px)`.
+pub fn parse_media_query(query: &str) -> Result<MediaList<'static>> {
+    let css = format!("@media {query} {{}}");
+    let sheet = StyleSheet::parse(&css, ParserOptions::default()).map_err(|x| x.into_owned())?;
+    let mut rules = sheet.rules.0;
+    ensure!(rules.len() == 1, "Invalid media query: {query:?}");
+    let query = match rules.pop() {
+        Some(CssRule::Media(rule)) => rule.query.into_owned(),
+        _ => bail!("Invalid media query: {query:?}"),
+    };
+    Ok(query)
+}
+
+/// Returns the rule list to place the `@font-face` rules for a font family in, wrapping them in
+/// an `@media` rule if the family has a media query.
+fn font_face_target<'a, 'b>(
+    ctx: &RewriteContext,
+    rules: &'b mut Vec<CssRule<'a>>,
+    family: &str,
+) -> &'b mut Vec<CssRule<'a>> {
+    let Some((_, query)) = ctx
+        .family_media
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(family))
+    else {
+        return rules;
+    };
+    let query = match parse_media_query(query) {
+        Ok(query) => query,
+        Err(e) => {
+            warn!("Ignoring media query for {family}: {e}");
+            return rules;
+        }
+    };
+
+    let idx = match rules
+        .iter()
+        .position(|x| matches!(x, CssRule::Media(rule) if rule.query == query))
+    {
+        Some(idx) => idx,
+        None => {
+            rules.push(CssRule::Media(MediaRule {
+                query,
+                rules: CssRuleList(vec![]),
+                loc: DEFAULT_LOC,
+            }));
+            rules.len() - 1
+        }
+    };
+    match &mut rules[idx] {
+        CssRule::Media(rule) => &mut rule.rules.0,
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the family name a generated font is declared under.
+fn generated_family_name(ctx: &RewriteContext, family: &str) -> String {
+    match &ctx.family_suffix {
+        Some(suffix) => format!("{family}{suffix}"),
+        None => family.to_string(),
+    }
+}
+
+/// Returns the `src` entries for a subset, in the order browsers should try them.
+///
+/// Each generated format is listed with its `format()` hint, so browsers skip formats they do not
+/// support without downloading them.
+fn font_face_sources<'a>(
+    ctx: &RewriteContext,
+    store_uris: &[Cow<str>],
+    subset: &SubsetInfo,
+) -> Vec<Source<'a>> {
+    let mut sources = Vec::new();
+    let mut push_source = |url: String, format: FontFormat<'a>| {
+        sources.push(Source::Url(UrlSource {
+            url: Url { url: url.into(), loc: DEFAULT_LOC_CSS },
+            format: (!ctx.omit_format_hint).then_some(format),
+            tech: vec![],
+        }));
+    };
+
+    let woff2_url = match ctx.inline_below {
+        Some(limit) if subset.woff2_data().len() < limit => {
+            format!("data:font/woff2;base64,{}", STANDARD.encode(subset.woff2_data()))
+        }
+        _ => store_url(store_uris, subset.woff2_file_name()),
+    };
+    push_source(woff2_url, FontFormat::WOFF2);
+    if let Some(woff_file_name) = subset.woff_file_name() {
+        push_source(store_url(store_uris, woff_file_name), FontFormat::WOFF);
+    }
+
+    sources
+}
+
 fn generate_font_face_stylesheet<'a, 'b>(
     ctx: &RewriteContext,
-    store_uri: &str,
+    store_uris: &[Cow<str>],
     used_stacks: Option<&WyHashSet<Arc<[ArcStr]>>>,
     fallback_needed: bool,
 ) -> StyleSheet<'a, 'b> {
     let mut sheet = StyleSheet::new(vec![], CssRuleList(vec![]), ParserOptions::default());
-    let store_prefix = if store_uri.is_empty() {
-        String::new()
-    } else {
-        format!("{store_uri}/")
-    };
     'font_loop: for font in &ctx.webfonts {
         if font.font_family() == &ctx.fallback_font_name {
             if !fallback_needed {
@@ -96,13 +226,20 @@ fn generate_font_face_stylesheet<'a, 'b>(
             font_face
                 .properties
                 .push(FontFaceProperty::FontFamily(FontFamily::FamilyName(
-                    font.font_family().to_string().into(),
+                    generated_family_name(ctx, font.font_family()).into(),
                 )));
             font_face.properties.push(FontFaceProperty::FontStyle(
-                match font.parsed_font_style() {
-                    FontStyle::Regular => CssFontStyle::Normal,
-                    FontStyle::Italic => CssFontStyle::Italic,
-                    FontStyle::Oblique => {
+                match (font.parsed_font_style(), font.slant_range()) {
+                    (FontStyle::Italic, _) => CssFontStyle::Italic,
+                    (_, Some(slant)) => {
+                        // The `slnt` axis uses negative angles for clockwise slants.
+                        CssFontStyle::Oblique(Size2D(
+                            Angle::Deg(-*slant.end()),
+                            Angle::Deg(-*slant.start()),
+                        ))
+                    }
+                    (FontStyle::Regular, None) => CssFontStyle::Normal,
+                    (FontStyle::Oblique, None) => {
                         // TODO: Figure out how to grab the proper Oblique angle
                         CssFontStyle::Oblique(Size2D(Angle::zero(), Angle::zero()))
                     }
@@ -111,6 +248,26 @@ fn generate_font_face_stylesheet<'a, 'b>(
             font_face
                 .properties
                 .push(FontFaceProperty::FontWeight(weight_range.clone()));
+            if let Some(width) = font.width_range() {
+                font_face
+                    .properties
+                    .push(FontFaceProperty::FontStretch(Size2D(
+                        FontStretch::Percentage(Percentage(*width.start() / 100.0)),
+                        FontStretch::Percentage(Percentage(*width.end() / 100.0)),
+                    )));
+            }
+            font_face
+                .properties
+                .push(ident_property("font-display", ctx.font_display.as_css()));
+            if ctx.emit_metric_overrides {
+                if let Some(metrics) = font.metrics() {
+                    font_face.properties.extend([
+                        percentage_property("ascent-override", metrics.ascent),
+                        percentage_property("descent-override", metrics.descent),
+                        percentage_property("line-gap-override", metrics.line_gap),
+                    ]);
+                }
+            }
             font_face.properties.push(FontFaceProperty::UnicodeRange(
                 subset
                     .unicode_ranges()
@@ -120,22 +277,107 @@ fn generate_font_face_stylesheet<'a, 'b>(
             ));
             font_face
                 .properties
-                .push(FontFaceProperty::Source(vec![Source::Url(UrlSource {
-                    url: Url {
-                        url: format!("{store_prefix}{}", subset.woff2_file_name()).into(),
-                        loc: DEFAULT_LOC_CSS,
-                    },
-                    format: Some(FontFormat::WOFF2),
-                    tech: vec![],
-                })]));
-            sheet.rules.0.push(CssRule::FontFace(font_face));
+                .push(FontFaceProperty::Source(font_face_sources(ctx, store_uris, subset)));
+            let target = font_face_target(ctx, &mut sheet.rules.0, font.font_family());
+            push_font_face(target, font_face);
         }
     }
     sheet
 }
 
+/// Returns the descriptors of a `@font-face` rule that must match for it to be merged with
+/// another rule. This is every descriptor except `unicode-range`, including `src`.
+fn font_face_merge_key<'a, 'b>(
+    rule: &'a FontFaceRule<'b>,
+) -> impl Iterator<Item = &'a FontFaceProperty<'b>> {
+    rule.properties
+        .iter()
+        .filter(|x| !matches!(x, FontFaceProperty::UnicodeRange(_)))
+}
+
+/// Pushes a `@font-face` rule, merging it into the previous rule if their merge keys (see
+/// [`font_face_merge_key`]) are equal, by combining their `unicode-range` descriptors.
+///
+/// As the key includes `src`, this only happens when consecutive rules use the same files, such
+/// as subsets deduplicated between fonts. Only the previous rule is considered, so the order of
+/// the rules is unchanged.
+fn push_font_face<'a>(rules: &mut Vec<CssRule<'a>>, font_face: FontFaceRule<'a>) {
+    fn ranges<'a, 'b>(rule: &'a mut FontFaceRule<'b>) -> Option<&'a mut Vec<UnicodeRange>> {
+        rule.properties.iter_mut().find_map(|x| match x {
+            FontFaceProperty::UnicodeRange(ranges) => Some(ranges),
+            _ => None,
+        })
+    }
+
+    if let Some(CssRule::FontFace(existing)) = rules.last_mut() {
+        if font_face_merge_key(existing).eq(font_face_merge_key(&font_face)) {
+            let mut font_face = font_face;
+            match (ranges(existing), ranges(&mut font_face)) {
+                (Some(existing), Some(new)) => {
+                    existing.append(new);
+                    existing.sort_by_key(|x| x.start);
+                    let mut merged: Vec<UnicodeRange> = Vec::new();
+                    for range in existing.drain(..) {
+                        match merged.last_mut() {
+                            Some(last) if range.start <= last.end.saturating_add(1) => {
+                                last.end = last.end.max(range.end);
+                            }
+                            _ => merged.push(range),
+                        }
+                    }
+                    *existing = merged;
+                }
+                // a rule without `unicode-range` covers every character
+                (Some(_), None) => *existing = font_face,
+                (None, _) => {}
+            }
+            return;
+        }
+    }
+    rules.push(CssRule::FontFace(font_face));
+}
+
+/// Renames the generated families in a font family list, and adds the fallback font before any
+/// generic families if the list needs it.
+fn rewrite_family_list(
+    ctx: &RewriteContext,
+    families: &ParsedCssRule<Arc<[ArcStr]>>,
+    family: &mut Vec<FontFamily>,
+) -> bool {
+    let mut rewritten = false;
+    if ctx.family_suffix.is_some() {
+        for name in family.iter_mut() {
+            if let FontFamily::FamilyName(name) = name {
+                let is_generated = ctx
+                    .webfonts
+                    .iter()
+                    .any(|x| x.font_family().eq_ignore_ascii_case(name));
+                if is_generated {
+                    *name = generated_family_name(ctx, name).into();
+                    rewritten = true;
+                }
+            }
+        }
+    }
+    if let ParsedCssRule::Override(families) = families {
+        if ctx.fallback_info.contains_key(families) {
+            let idx = family
+                .iter()
+                .position(|x| matches!(x, FontFamily::Generic(_)))
+                .unwrap_or(family.len());
+            family.insert(
+                idx,
+                FontFamily::FamilyName(generated_family_name(ctx, &ctx.fallback_font_name).into()),
+            );
+            rewritten = true;
+        }
+    }
+    rewritten
+}
+
 fn rewrite_properties_for_fallback(
     ctx: &RewriteContext,
+    generics: &GenericFamilies,
     properties: &mut DeclarationBlock,
 ) -> bool {
     // We do NOT warn about unrecgonized CSS here, because that should be done in the `gather_css`
@@ -149,18 +391,20 @@ fn rewrite_properties_for_fallback(
     {
         match property {
             Property::FontFamily(family) => {
-                let ParsedCssRule::Override(families) = parse_font_families(&family) else {
+                let families = parse_font_families(&family, generics);
+                if !matches!(families, ParsedCssRule::Override(_)) {
                     continue;
-                };
+                }
                 let init_len = family.len();
                 family.retain(|x| matches!(x, FontFamily::FamilyName(_)));
                 if init_len != family.len() {
                     rewritten = true;
                 }
-                if ctx.fallback_info.contains_key(&families) {
-                    family.push(FontFamily::FamilyName(ctx.fallback_font_name.clone().into()));
-                    rewritten = true;
-                }
+                rewritten |= rewrite_family_list(ctx, &families, family);
+            }
+            Property::Font(font) => {
+                let families = parse_font_families(&font.family, generics);
+                rewritten |= rewrite_family_list(ctx, &families, &mut font.family);
             }
             _ => {}
         }
@@ -168,15 +412,22 @@ fn rewrite_properties_for_fallback(
     rewritten
 }
 
-fn rewrite_for_fallback(ctx: &RewriteContext, css: &mut [CssRule]) -> bool {
+fn rewrite_for_fallback(
+    ctx: &RewriteContext,
+    generics: &GenericFamilies,
+    css: &mut [CssRule],
+) -> bool {
     let mut rewritten = false;
     for rule in css {
         match rule {
             CssRule::Media(media_query) => {
-                rewritten |= rewrite_for_fallback(ctx, &mut media_query.rules.0);
+                rewritten |= rewrite_for_fallback(ctx, generics, &mut media_query.rules.0);
+            }
+            CssRule::LayerBlock(layer) => {
+                rewritten |= rewrite_for_fallback(ctx, generics, &mut layer.rules.0);
             }
             CssRule::Style(rule) => {
-                rewritten |= rewrite_properties_for_fallback(ctx, &mut rule.declarations);
+                rewritten |= rewrite_properties_for_fallback(ctx, generics, &mut rule.declarations);
             }
             _ => {}
         }
@@ -187,17 +438,21 @@ fn rewrite_for_fallback(ctx: &RewriteContext, css: &mut [CssRule]) -> bool {
 fn add_font_faces(
     css: &mut StyleSheet,
     ctx: &RewriteContext,
-    store_url: &str,
+    store_uris: &[Cow<str>],
     used_stacks: Option<&WyHashSet<Arc<[ArcStr]>>>,
     fallback_needed: bool,
 ) {
-    let sheet = generate_font_face_stylesheet(ctx, store_url, used_stacks, fallback_needed);
+    let sheet = generate_font_face_stylesheet(ctx, store_uris, used_stacks, fallback_needed);
     css.rules.0.extend(sheet.rules.0);
 }
 
-fn find_store_uri<'a>(ctx: &'a RewriteContext, root: &RelaWebroot) -> Result<Cow<'a, str>> {
-    if let Some(uri) = &ctx.store_uri {
-        Ok(Cow::Borrowed(uri.as_str()))
+fn find_store_uris<'a>(ctx: &'a RewriteContext, root: &RelaWebroot) -> Result<Vec<Cow<'a, str>>> {
+    if !ctx.store_uris.is_empty() {
+        Ok(ctx
+            .store_uris
+            .iter()
+            .map(|x| Cow::Borrowed(x.as_str()))
+            .collect())
     } else {
         if !is_superpath(root.root().root(), &ctx.store_path)? {
             bail!(
@@ -205,12 +460,13 @@ fn find_store_uri<'a>(ctx: &'a RewriteContext, root: &RelaWebroot) -> Result<Cow
                  or `--store-uri` must be provided."
             );
         }
-        Ok(Cow::Owned(get_relative_from(&root.file_name(), &ctx.store_path)?))
+        Ok(vec![Cow::Owned(get_relative_from(&root.file_name(), &ctx.store_path)?)])
     }
 }
 
 fn rewrite_css(
     ctx: &RewriteContext,
+    generics: &GenericFamilies,
     root: &RelaWebroot,
     append_fonts: bool,
     used_stacks: Option<&WyHashSet<Arc<[ArcStr]>>>,
@@ -219,22 +475,21 @@ fn rewrite_css(
     let data = std::fs::read_to_string(root.file_name())?;
     let mut sheet =
         StyleSheet::parse(&data, ParserOptions::default()).map_err(|x| x.into_owned())?;
-    let mut rewritten = rewrite_for_fallback(ctx, &mut sheet.rules.0);
+    let mut rewritten = rewrite_for_fallback(ctx, generics, &mut sheet.rules.0);
     if append_fonts {
-        let store_uri = if let Some(uri) = &ctx.store_uri {
-            Cow::Borrowed(uri.as_str())
-        } else {
-            Cow::Owned(get_relative_from(&root.file_name(), &ctx.store_path)?)
-        };
+        let store_uris = find_store_uris(ctx, root)?;
         debug!(
-            "(Appending fonts) Store URI for {} -> {}: {store_uri}",
+            "(Appending fonts) Store URI for {} -> {}: {}",
             root.file_name().display(),
             ctx.store_path.display(),
+            store_uris.join(", "),
         );
-        add_font_faces(&mut sheet, ctx, &find_store_uri(ctx, root)?, used_stacks, fallback_needed);
+        add_font_faces(&mut sheet, ctx, &store_uris, used_stacks, fallback_needed);
         rewritten = true;
     }
-    if rewritten {
+    if rewritten && ctx.dry_run {
+        info!("Would write modified CSS to {}.", root.file_name().display());
+    } else if rewritten {
         info!("Writing modified CSS to {}...", root.file_name().display());
         std::fs::write(root.file_name(), sheet.to_css(printer())?.code)?;
     } else {
@@ -251,27 +506,159 @@ fn generate_css(
 ) -> Result<()> {
     let sheet = generate_font_face_stylesheet(
         ctx,
-        &find_store_uri(ctx, root)?,
+        &find_store_uris(ctx, root)?,
         used_stacks,
         fallback_needed,
     );
-    info!("Writing @font-face CSS to {}...", root.file_name().display());
-    std::fs::write(root.file_name(), sheet.to_css(printer())?.code)?;
+    if ctx.dry_run {
+        info!("Would write @font-face CSS to {}.", root.file_name().display());
+    } else {
+        info!("Writing @font-face CSS to {}...", root.file_name().display());
+        std::fs::write(root.file_name(), sheet.to_css(printer())?.code)?;
+    }
     Ok(())
 }
 
 pub fn generate_font_css(ctx: &RewriteContext) -> Result<String> {
-    let Some(store_uri) = &ctx.store_uri else {
+    if ctx.store_uris.is_empty() {
         bail!("`--store_uri` is required for generating detached font CSS.")
+    }
+    let store_uris: Vec<_> = ctx
+        .store_uris
+        .iter()
+        .map(|x| Cow::Borrowed(x.as_str()))
+        .collect();
+    let sheet = generate_font_face_stylesheet(ctx, &store_uris, None, false);
+    let mut options = printer();
+    options.minify = ctx.minify_css;
+    Ok(sheet.to_css(options)?.code)
+}
+
+/// Returns `<link rel="preload">` tags for the base subset of each font family, excluding the
+/// fallback font.
+///
+/// Only one style is preloaded per family, preferring the regular style at weight 400, as
+/// preloading every style would delay the rest of the page.
+pub fn preload_links(ctx: &RewriteContext, store_uris: &[Cow<str>]) -> Vec<String> {
+    let mut families: Vec<&WebfontInfo> = Vec::new();
+    for font in &ctx.webfonts {
+        if font.font_family() == ctx.fallback_font_name {
+            continue;
+        }
+        let score = |x: &WebfontInfo| {
+            (x.parsed_font_style() == FontStyle::Regular, x.weight_range().contains(&400))
+        };
+        match families
+            .iter_mut()
+            .find(|x| x.font_family() == font.font_family())
+        {
+            Some(existing) if score(font) > score(existing) => *existing = font,
+            Some(_) => {}
+            None => families.push(font),
+        }
+    }
+
+    let mut links = Vec::new();
+    for font in families {
+        for subset in font.subsets().iter().filter(|x| x.is_base()) {
+            if matches!(ctx.inline_below, Some(limit) if subset.woff2_data().len() < limit) {
+                // already embedded in the CSS
+                continue;
+            }
+            let href = store_url(store_uris, subset.woff2_file_name())
+                .replace('&', "&amp;")
+                .replace('"', "&quot;");
+            let integrity = match ctx.preload_integrity {
+                true => format!(" integrity=\"{}\"", subset.integrity()),
+                false => String::new(),
+            };
+            links.push(format!(
+                "<link rel=\"preload\" as=\"font\" type=\"font/woff2\" crossorigin \
+                 href=\"{href}\"{integrity}>"
+            ));
+        }
+    }
+    links
+}
+
+pub fn generate_preload_links(ctx: &RewriteContext) -> Result<Vec<String>> {
+    if ctx.store_uris.is_empty() {
+        bail!("`--store_uri` is required for generating detached preload links.")
+    }
+    let store_uris: Vec<_> = ctx
+        .store_uris
+        .iter()
+        .map(|x| Cow::Borrowed(x.as_str()))
+        .collect();
+    Ok(preload_links(ctx, &store_uris))
+}
+
+/// Adds preload links for the generated fonts to the `<head>` of an HTML document, skipping links
+/// that are already present. Returns whether the document was modified.
+pub fn add_preload_links(
+    ctx: &RewriteContext,
+    root: &RelaWebroot,
+    document: &NodeRef,
+) -> Result<bool> {
+    let Ok(head) = document.select_first("head") else {
+        warn!("{} has no <head>, not adding preload links.", root.file_name().display());
+        return Ok(false);
+    };
+    let existing: WyHashSet<String> = head
+        .as_node()
+        .select("link[rel=preload]")
+        .unwrap()
+        .filter_map(|x| x.attributes.borrow().get("href").map(str::to_string))
+        .collect();
+
+    let links = parse_html().one(preload_links(ctx, &find_store_uris(ctx, root)?).concat());
+    let mut modified = false;
+    for link in links.select("link").unwrap().collect::<Vec<_>>() {
+        let href = link.attributes.borrow().get("href").map(str::to_string);
+        if href.is_some_and(|x| !existing.contains(&x)) {
+            head.as_node().append(link.as_node().clone());
+            modified = true;
+        }
+    }
+    Ok(modified)
+}
+
+/// Adds a link to a stylesheet in the root of the webroot to the `<head>` of an HTML document,
+/// unless it already links a stylesheet marked with `mkwebfont-out`. Returns whether the document
+/// was modified.
+pub fn add_stylesheet_link(root: &RelaWebroot, document: &NodeRef, name: &str) -> Result<bool> {
+    let Ok(head) = document.select_first("head") else {
+        warn!("{} has no <head>, not adding a stylesheet link.", root.file_name().display());
+        return Ok(false);
     };
-    let sheet = generate_font_face_stylesheet(ctx, &store_uri, None, false);
-    Ok(sheet.to_css(printer())?.code)
+    if head
+        .as_node()
+        .select_first("link[rel~=mkwebfont-out]")
+        .is_ok()
+    {
+        return Ok(false);
+    }
+
+    // the stylesheet may not have been written yet, so only the webroot itself is resolved
+    let href = match get_relative_from(root.file_name(), root.root().root())?.as_str() {
+        "" => name.to_string(),
+        dir => format!("{dir}/{name}"),
+    };
+    let href = href.replace('&', "&amp;").replace('"', "&quot;");
+    let link = parse_html().one(format!("<link rel=\"stylesheet mkwebfont-out\" href=\"{href}\">"));
+    let link = link.select_first("link").unwrap();
+    head.as_node().append(link.as_node().clone());
+    Ok(true)
 }
 
-pub fn rewrite_style_attr(ctx: &RewriteContext, style: &str) -> Result<Option<String>> {
+pub fn rewrite_style_attr(
+    ctx: &RewriteContext,
+    generics: &GenericFamilies,
+    style: &str,
+) -> Result<Option<String>> {
     match DeclarationBlock::parse_string(style, ParserOptions::default()) {
         Ok(mut block) => {
-            if rewrite_properties_for_fallback(ctx, &mut block) {
+            if rewrite_properties_for_fallback(ctx, generics, &mut block) {
                 Ok(Some(block.to_css_string(printer())?))
             } else {
                 Ok(None)
@@ -281,10 +668,14 @@ pub fn rewrite_style_attr(ctx: &RewriteContext, style: &str) -> Result<Option<St
     }
 }
 
-pub fn rewrite_style_tag(ctx: &RewriteContext, style: &str) -> Result<Option<String>> {
+pub fn rewrite_style_tag(
+    ctx: &RewriteContext,
+    generics: &GenericFamilies,
+    style: &str,
+) -> Result<Option<String>> {
     let mut sheet =
         StyleSheet::parse(style, ParserOptions::default()).map_err(|x| x.into_owned())?;
-    if rewrite_for_fallback(ctx, &mut sheet.rules.0) {
+    if rewrite_for_fallback(ctx, generics, &mut sheet.rules.0) {
         Ok(Some(sheet.to_css(printer())?.code))
     } else {
         Ok(None)
@@ -293,6 +684,7 @@ pub fn rewrite_style_tag(ctx: &RewriteContext, style: &str) -> Result<Option<Str
 
 pub fn process_css_path(
     ctx: &RewriteContext,
+    generics: &GenericFamilies,
     root: &RelaWebroot,
     append_fonts: bool,
     used_stacks: Option<&WyHashSet<Arc<[ArcStr]>>>,
@@ -305,6 +697,6 @@ pub fn process_css_path(
             generate_css(ctx, root, used_stacks, true)
         }
     } else {
-        rewrite_css(ctx, root, append_fonts, used_stacks, true)
+        rewrite_css(ctx, generics, root, append_fonts, used_stacks, true)
     }
 }
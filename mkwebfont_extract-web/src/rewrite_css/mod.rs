@@ -1,11 +1,14 @@
 mod css_ops;
 
 use crate::{
+    gather_css::GenericFamilies,
     utils::inner_html,
+    warnings,
     webroot::{RelaWebroot, Webroot},
 };
 use anyhow::Result;
 use arcstr::ArcStr;
+use bincode::{Decode, Encode};
 use kuchikiki::{iter::NodeIterator, parse_html, traits::TendrilSink, NodeRef, Selectors};
 use mkwebfont_common::{
     character_set::CharacterSet,
@@ -17,40 +20,176 @@ use std::{
     path::{Path, PathBuf},
     sync::{Arc, LazyLock},
 };
-use tracing::{warn, Instrument};
+use tracing::{info, warn, Instrument};
+
+/// The name of the stylesheet generated in the root of a webroot for pages that do not link any
+/// stylesheet the `@font-face` rules can be added to.
+pub(crate) const GENERATED_CSS_NAME: &str = "mkwebfont-fonts.css";
 
 #[derive(Default, Debug, Clone)]
 pub struct RewriteTargets {
     targets: WyHashMap<Arc<Path>, WebrootRewriteTargets>,
 }
+impl RewriteTargets {
+    pub(crate) fn to_cached(&self) -> Vec<CachedRewriteTargets> {
+        fn paths(set: &WyHashSet<Arc<Path>>) -> Vec<PathBuf> {
+            set.iter().map(|x| x.to_path_buf()).collect()
+        }
+        self.targets
+            .iter()
+            .map(|(root, targets)| CachedRewriteTargets {
+                root: root.to_path_buf(),
+                rewrite_html_style: paths(&targets.rewrite_html_style),
+                rewrite_css_path: paths(&targets.rewrite_css_path),
+                rewrite_css_path_fonts: paths(&targets.rewrite_css_path_fonts),
+                preload_html: paths(&targets.preload_html),
+                link_html: paths(&targets.link_html),
+                used_stacks: targets
+                    .used_stacks
+                    .iter()
+                    .map(|(path, stacks)| {
+                        let stacks = stacks
+                            .iter()
+                            .map(|x| x.iter().map(|x| x.to_string()).collect())
+                            .collect();
+                        (path.to_path_buf(), stacks)
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn from_cached(cached: Vec<CachedRewriteTargets>) -> RewriteTargets {
+        fn paths(list: Vec<PathBuf>) -> WyHashSet<Arc<Path>> {
+            list.into_iter().map(Arc::from).collect()
+        }
+        let mut out = RewriteTargets::default();
+        for targets in cached {
+            let used_stacks = targets
+                .used_stacks
+                .into_iter()
+                .map(|(path, stacks)| {
+                    let stacks = stacks
+                        .into_iter()
+                        .map(|x| x.into_iter().map(ArcStr::from).collect())
+                        .collect();
+                    (Arc::from(path), stacks)
+                })
+                .collect();
+            out.targets
+                .insert(targets.root.into(), WebrootRewriteTargets {
+                    rewrite_html_style: paths(targets.rewrite_html_style),
+                    rewrite_css_path: paths(targets.rewrite_css_path),
+                    rewrite_css_path_fonts: paths(targets.rewrite_css_path_fonts),
+                    preload_html: paths(targets.preload_html),
+                    link_html: paths(targets.link_html),
+                    used_stacks,
+                });
+        }
+        out
+    }
+}
+
+/// The rewrite targets for one webroot, in the form stored in the webroot cache.
+#[derive(Debug, Encode, Decode)]
+pub(crate) struct CachedRewriteTargets {
+    root: PathBuf,
+    rewrite_html_style: Vec<PathBuf>,
+    rewrite_css_path: Vec<PathBuf>,
+    rewrite_css_path_fonts: Vec<PathBuf>,
+    preload_html: Vec<PathBuf>,
+    link_html: Vec<PathBuf>,
+    used_stacks: Vec<(PathBuf, Vec<Vec<String>>)>,
+}
 
 #[derive(Default, Debug, Clone)]
 struct WebrootRewriteTargets {
     rewrite_html_style: WyHashSet<Arc<Path>>,
     rewrite_css_path: WyHashSet<Arc<Path>>,
     rewrite_css_path_fonts: WyHashSet<Arc<Path>>,
+    /// HTML files that link the stylesheet the `@font-face` rules are added to.
+    preload_html: WyHashSet<Arc<Path>>,
+    /// HTML files that link no stylesheet, which a link to the generated stylesheet is added to.
+    link_html: WyHashSet<Arc<Path>>,
     used_stacks: WyHashMap<Arc<Path>, WyHashSet<Arc<[ArcStr]>>>,
 }
 
+/// The value of the `font-display` descriptor in generated `@font-face` rules, controlling how
+/// text is rendered while a font is loading.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FontDisplay {
+    Auto,
+    Block,
+    #[default]
+    Swap,
+    Fallback,
+    Optional,
+}
+impl FontDisplay {
+    /// Returns the CSS keyword for this value.
+    pub fn as_css(&self) -> &'static str {
+        match self {
+            FontDisplay::Auto => "auto",
+            FontDisplay::Block => "block",
+            FontDisplay::Swap => "swap",
+            FontDisplay::Fallback => "fallback",
+            FontDisplay::Optional => "optional",
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct RewriteContext {
     pub fallback_font_name: String,
     pub fallback_info: WyHashMap<Arc<[ArcStr]>, CharacterSet>,
     pub webfonts: Vec<Arc<WebfontInfo>>,
     pub store_path: PathBuf,
-    pub store_uri: Option<String>,
+    /// The URIs the store can be accessed at. Files are distributed between them by file name.
+    pub store_uris: Vec<String>,
+    pub emit_metric_overrides: bool,
+    pub omit_format_hint: bool,
+    /// Whether detached font CSS is minified. Rewritten webroot files are never minified.
+    pub minify_css: bool,
+    /// Subsets smaller than this many bytes are embedded in the CSS as `data:` URIs.
+    pub inline_below: Option<usize>,
+    /// A suffix appended to the family name of every generated font.
+    pub family_suffix: Option<String>,
+    /// Media queries to wrap the `@font-face` rules of certain font families in, as pairs of
+    /// family names and media queries.
+    pub family_media: Vec<(String, String)>,
+    /// The `font-display` descriptor used in every generated `@font-face` rule.
+    pub font_display: FontDisplay,
+    /// Whether preload links for the base subset of each font family are added to the `<head>`
+    /// of HTML files that use the generated fonts.
+    pub preload_links: bool,
+    /// Whether preload links carry an `integrity` attribute.
+    pub preload_integrity: bool,
+    /// Whether the files that would be modified are only reported, rather than written.
+    pub dry_run: bool,
 }
 
-fn process_html_path(ctx: &RewriteContext, root: &RelaWebroot) -> Result<()> {
+fn process_html_path(
+    ctx: &RewriteContext,
+    generics: &GenericFamilies,
+    root: &RelaWebroot,
+    preload: bool,
+    link: bool,
+) -> Result<()> {
     static SELECTOR: LazyLock<Selectors> =
         LazyLock::new(|| Selectors::compile("style,*[style]").unwrap());
 
     let document = parse_html().one(std::fs::read_to_string(&root.file_name())?);
     let mut modified = false;
+    if link {
+        modified |= css_ops::add_stylesheet_link(root, &document, GENERATED_CSS_NAME)?;
+    }
+    if preload {
+        modified |= css_ops::add_preload_links(ctx, root, &document)?;
+    }
     for elem in SELECTOR.filter(document.inclusive_descendants().elements()) {
         if elem.name.local.as_bytes() == b"style" {
             let text = inner_html(elem.as_node());
-            if let Some(text) = css_ops::rewrite_style_tag(ctx, &text)? {
+            if let Some(text) = css_ops::rewrite_style_tag(ctx, generics, &text)? {
                 elem.as_node().children().for_each(|x| x.detach());
                 elem.as_node().append(NodeRef::new_text(text));
                 modified = true;
@@ -59,14 +198,16 @@ fn process_html_path(ctx: &RewriteContext, root: &RelaWebroot) -> Result<()> {
 
         let mut attrs = elem.attributes.borrow_mut();
         if let Some(text) = attrs.get("style") {
-            if let Some(text) = css_ops::rewrite_style_attr(ctx, text)? {
+            if let Some(text) = css_ops::rewrite_style_attr(ctx, generics, text)? {
                 attrs.insert("style", text);
                 modified = true;
             }
         }
     }
 
-    if modified {
+    if modified && ctx.dry_run {
+        info!("Would write modified HTML to {}.", root.file_name().display());
+    } else if modified {
         document.serialize_to_file(root.file_name())?;
     }
 
@@ -76,6 +217,7 @@ fn process_html_path(ctx: &RewriteContext, root: &RelaWebroot) -> Result<()> {
 async fn perform_rewrite_for_root(
     targets: &WebrootRewriteTargets,
     webroot: &Webroot,
+    generics: Arc<GenericFamilies>,
     ctx: Arc<RewriteContext>,
 ) -> Result<()> {
     let mut joins = JoinSet::new();
@@ -86,37 +228,97 @@ async fn perform_rewrite_for_root(
         .chain(targets.rewrite_css_path_fonts.iter().map(|x| (x, true)))
     {
         let ctx = ctx.clone();
+        let generics = generics.clone();
         let root = webroot.rela(&path)?;
 
         let used_stacks = targets.used_stacks.get(path).cloned();
         joins.spawn(
-            async move { css_ops::process_css_path(&ctx, &root, append_fonts, used_stacks.as_ref()) }
-                .in_current_span(),
+            async move {
+                css_ops::process_css_path(
+                    &ctx,
+                    &generics,
+                    &root,
+                    append_fonts,
+                    used_stacks.as_ref(),
+                )
+            }
+            .in_current_span(),
         );
     }
-    for path in &targets.rewrite_html_style {
+    let preload_html = match ctx.preload_links {
+        true => targets.preload_html.clone(),
+        false => WyHashSet::default(),
+    };
+    let html_paths: WyHashSet<_> = targets
+        .rewrite_html_style
+        .iter()
+        .chain(&preload_html)
+        .chain(&targets.link_html)
+        .collect();
+    for path in html_paths {
         let ctx = ctx.clone();
+        let generics = generics.clone();
         let root = webroot.rela(&path)?;
-        joins.spawn(async move { process_html_path(&ctx, &root) }.in_current_span());
+        let preload = preload_html.contains(path);
+        let link = targets.link_html.contains(path);
+        joins.spawn(
+            async move { process_html_path(&ctx, &generics, &root, preload, link) }
+                .in_current_span(),
+        );
     }
     joins.join().await?;
     Ok(())
 }
 
+/// Checks that a media query list (such as `(min-width: 800px)`) can be parsed.
+pub fn validate_media_query(query: &str) -> Result<()> {
+    css_ops::parse_media_query(query)?;
+    Ok(())
+}
+
 impl RewriteContext {
     pub fn generate_font_css(&self) -> Result<String> {
         css_ops::generate_font_css(self)
     }
+
+    /// Returns `<link rel="preload">` tags for the base subset of each font family, referencing
+    /// the store URI.
+    pub fn generate_preload_links(&self) -> Result<Vec<String>> {
+        css_ops::generate_preload_links(self)
+    }
+}
+
+/// Renders the `@font-face` rules for a single webfont, without a full [`RewriteContext`].
+pub trait RenderWebfontCss {
+    /// Renders the `@font-face` rules for this webfont, with the store accessible at the given
+    /// URI.
+    fn render_css(&self, store_uri: &str) -> Result<String>;
+}
+impl RenderWebfontCss for WebfontInfo {
+    fn render_css(&self, store_uri: &str) -> Result<String> {
+        let ctx = RewriteContext {
+            webfonts: vec![Arc::new(self.clone())],
+            store_uris: vec![store_uri.to_string()],
+            ..RewriteContext::default()
+        };
+        ctx.generate_font_css()
+    }
 }
 
-pub async fn perform_rewrite(targets: &RewriteTargets, ctx: Arc<RewriteContext>) -> Result<()> {
+pub async fn perform_rewrite(
+    targets: &RewriteTargets,
+    generics: &GenericFamilies,
+    ctx: Arc<RewriteContext>,
+) -> Result<()> {
+    let generics = Arc::new(generics.clone());
     let mut joins = JoinSet::new();
     for (root, targets) in &targets.targets {
         let targets = targets.clone();
         let webroot = Webroot::new(root.to_path_buf())?;
+        let generics = generics.clone();
         let ctx = ctx.clone();
         joins.spawn(
-            async move { perform_rewrite_for_root(&targets, &webroot, ctx).await }
+            async move { perform_rewrite_for_root(&targets, &webroot, generics, ctx).await }
                 .in_current_span(),
         );
     }
@@ -152,11 +354,18 @@ pub fn find_css_for_rewrite(
             }
             b"link" => {
                 let attrs = elem.attributes.borrow();
-                let path = root.resolve(attrs.get("href").unwrap())?;
-                if attrs.get("rel").unwrap().contains("mkwebfont-out") {
-                    css_list_fonts.push(path);
-                } else {
-                    css_list.push(path);
+                let href = attrs.get("href").unwrap();
+                match root.resolve(href) {
+                    Ok(path) => {
+                        if attrs.get("rel").unwrap().contains("mkwebfont-out") {
+                            css_list_fonts.push(path);
+                        } else {
+                            css_list.push(path);
+                        }
+                    }
+                    Err(e) => {
+                        warn!(target: warnings::MISSING_RESOURCE, "Could not resolve stylesheet '{href}': {e}")
+                    }
                 }
             }
             _ => {}
@@ -189,10 +398,17 @@ pub fn find_css_for_rewrite(
             css_list_fonts.push(css_list.remove(0));
         }
     }
+    if css_list_fonts.is_empty() && !used_stacks.is_empty() {
+        css_list_fonts.push(root.root().root().join(GENERATED_CSS_NAME));
+        root_target.link_html.insert(root.file_name().clone());
+    }
 
     for path in css_list {
         root_target.rewrite_css_path.insert(path.into());
     }
+    if !css_list_fonts.is_empty() {
+        root_target.preload_html.insert(root.file_name().clone());
+    }
     for path in css_list_fonts {
         if root_target.rewrite_css_path.contains(path.as_path()) {
             warn!("Path {} is used for @font-face generation only on some pages.", path.display());
@@ -0,0 +1,35 @@
+//! Categories for the warnings emitted while processing a webroot.
+//!
+//! Each category is a `tracing` target, so a category can be silenced by adding a directive such
+//! as `mkwebfont_extract_web::generic_family=error` to the subscriber's filter.
+
+use anyhow::{bail, Result};
+
+/// Generic font families (such as `sans-serif`) that are ignored, and font lists left empty
+/// without them.
+pub const GENERIC_FAMILY: &str = "mkwebfont_extract_web::generic_family";
+/// CSS properties or inline styles that could not be parsed.
+pub const UNPARSED: &str = "mkwebfont_extract_web::unparsed";
+/// CSS features that are parsed but not handled, such as nested or unrecognized rules.
+pub const UNSUPPORTED: &str = "mkwebfont_extract_web::unsupported";
+/// Stylesheets or other resources that could not be loaded.
+pub const MISSING_RESOURCE: &str = "mkwebfont_extract_web::missing_resource";
+
+/// The name of every warning category, along with its `tracing` target.
+pub const WARNING_CATEGORIES: &[(&str, &str)] = &[
+    ("generic-family", GENERIC_FAMILY),
+    ("unparsed", UNPARSED),
+    ("unsupported", UNSUPPORTED),
+    ("missing-resource", MISSING_RESOURCE),
+];
+
+/// Returns the `tracing` target for a warning category name, such as `generic-family`.
+pub fn warning_target(category: &str) -> Result<&'static str> {
+    match WARNING_CATEGORIES.iter().find(|x| x.0 == category) {
+        Some((_, target)) => Ok(target),
+        None => {
+            let names: Vec<_> = WARNING_CATEGORIES.iter().map(|x| x.0).collect();
+            bail!("Unknown warning category {category:?}. Valid categories: {}", names.join(", "))
+        }
+    }
+}
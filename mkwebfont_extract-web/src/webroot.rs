@@ -8,7 +8,7 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
 };
-use tracing::{debug, Instrument};
+use tracing::{debug, warn, Instrument};
 
 #[derive(Clone)]
 pub struct Webroot(Arc<WebrootData>);
@@ -47,13 +47,24 @@ impl Webroot {
             resolved.display()
         );
 
-        if !resolved.starts_with(&self.0.root) {
-            bail!("Resolved path '{resolved:?}' is not child of '{:?}'", self.0.root);
-        }
-
+        self.check_in_root(&resolved)?;
         Ok(resolved)
     }
 
+    /// Refuses any canonicalized path outside of the webroot, so references such as `../../etc`
+    /// in an untrusted site can never cause files outside of it to be read or rewritten.
+    fn check_in_root(&self, path: &Path) -> Result<()> {
+        if !path.starts_with(&self.0.root) {
+            warn!(
+                "Refusing to access '{}', as it is outside the webroot '{}'.",
+                path.display(),
+                self.0.root.display(),
+            );
+            bail!("Path '{path:?}' is not child of '{:?}'", self.0.root);
+        }
+        Ok(())
+    }
+
     async fn cache_read(&self, path: &Path) -> Result<ArcStr> {
         Ok(self
             .0
@@ -88,10 +99,7 @@ impl Webroot {
         let mut new_root = self.0.root.to_path_buf();
         new_root.push(rela_root);
         let path = new_root.canonicalize()?;
-
-        if !path.starts_with(&self.0.root) {
-            bail!("Relative path '{path:?}' is not child of '{:?}'", self.0.root);
-        }
+        self.check_in_root(&path)?;
 
         Ok(RelaWebroot {
             root: self.clone(),
@@ -0,0 +1,184 @@
+use crate::{
+    rewrite_css::{CachedRewriteTargets, RewriteTargets, GENERATED_CSS_NAME},
+    ExtractorConfig, FontStackInfo, TextSample, WebrootInfo,
+};
+use anyhow::Result;
+use arcstr::ArcStr;
+use bincode::{config::standard, Decode, Encode};
+use mkwebfont_common::{
+    character_set::{CharacterSet, CompressedCharacterSet},
+    compression::{zstd_compress, zstd_decompress},
+    hashing::hash_full,
+};
+use mkwebfont_fontops::font_info::{FontStyle, FontWeight};
+use std::{path::Path, time::UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// Incremented whenever the format of the cache or the results of the extractor change.
+const CACHE_VERSION: u32 = 7;
+
+#[derive(Debug, Encode, Decode)]
+struct CachedWebrootInfo {
+    key: String,
+    font_stacks: Vec<CachedFontStack>,
+    font_face_ranges: Vec<(String, CompressedCharacterSet)>,
+    targets: Vec<CachedRewriteTargets>,
+}
+
+#[derive(Debug, Encode, Decode)]
+struct CachedFontStack {
+    stack: Vec<String>,
+    samples: Vec<CachedTextSample>,
+}
+
+#[derive(Debug, Encode, Decode)]
+struct CachedTextSample {
+    styles: Vec<FontStyle>,
+    weights: Vec<u32>,
+    content: Vec<String>,
+}
+
+/// Returns whether a file in a webroot is read by the extractor, and so is part of the cache key.
+///
+/// Only HTML and CSS files are read. This leaves out the files mkwebfont writes into a webroot,
+/// such as the cache file, the store and the stylesheet generated for pages without one, which
+/// would otherwise invalidate the cache on every run.
+fn is_cache_input(root: &Path, cache: &Path, path: &Path) -> bool {
+    let is_input = path
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("html") || x.eq_ignore_ascii_case("css"));
+    is_input && path != cache && path != root.join(GENERATED_CSS_NAME)
+}
+
+/// Computes a key identifying the current state of a webroot, from the paths, sizes and
+/// modification times of the HTML and CSS files in it, along with the extractor configuration.
+fn cache_key(root: &Path, cache: &Path, config: &ExtractorConfig) -> Result<String> {
+    let cache = std::path::absolute(cache)?;
+    let abs_root = std::path::absolute(root)?;
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if is_cache_input(&abs_root, &cache, &std::path::absolute(entry.path())?) {
+                let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+                let path = entry.path();
+                let path = path.strip_prefix(root)?.display().to_string();
+                files.push(format!("{path}\0{}\0{modified}", metadata.len()));
+            }
+        }
+    }
+    files.sort();
+
+    let mut data = format!(
+        "{}\0{CACHE_VERSION}\0{}\0{}",
+        env!("CARGO_PKG_VERSION"),
+        config.flags.as_u64(),
+        root.canonicalize()?.display(),
+    );
+    for (generic, family) in config.generic_families.iter() {
+        data.push_str(&format!("\0{generic}={family}"));
+    }
+    for file in files {
+        data.push('\n');
+        data.push_str(&file);
+    }
+    Ok(hash_full(data.as_bytes()))
+}
+
+impl WebrootInfo {
+    /// Loads the information for a webroot from a cache file written by
+    /// [`WebrootInfo::save_cache`].
+    ///
+    /// Returns `None` if the cache file does not exist, or if any HTML or CSS file in the webroot
+    /// changed since it was written.
+    pub fn load_cache(
+        cache: &Path,
+        root: &Path,
+        config: &ExtractorConfig,
+    ) -> Result<Option<WebrootInfo>> {
+        if !cache.exists() {
+            return Ok(None);
+        }
+        let data = zstd_decompress(&std::fs::read(cache)?)?;
+        let cached: CachedWebrootInfo = match bincode::decode_from_slice(&data, standard()) {
+            Ok((cached, _)) => cached,
+            Err(e) => {
+                debug!("Could not decode webroot cache: {e}");
+                return Ok(None);
+            }
+        };
+        if cached.key != cache_key(root, cache, config)? {
+            info!("Webroot has changed since '{}' was written.", cache.display());
+            return Ok(None);
+        }
+
+        let font_stacks = cached
+            .font_stacks
+            .into_iter()
+            .map(|stack| FontStackInfo {
+                stack: stack.stack.into_iter().map(ArcStr::from).collect(),
+                samples: stack
+                    .samples
+                    .into_iter()
+                    .map(|sample| TextSample {
+                        used_styles: sample.styles.into_iter().collect(),
+                        used_weights: sample
+                            .weights
+                            .into_iter()
+                            .map(FontWeight::from_num)
+                            .collect(),
+                        content: sample.content.into_iter().map(ArcStr::from).collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let font_face_ranges = cached
+            .font_face_ranges
+            .into_iter()
+            .map(|(family, range)| (ArcStr::from(family), CharacterSet::decompress(&range)))
+            .collect();
+        Ok(Some(WebrootInfo {
+            font_stacks,
+            font_face_ranges,
+            targets: RewriteTargets::from_cached(cached.targets),
+            generic_families: config.generic_families.clone(),
+        }))
+    }
+
+    /// Writes the information for a webroot to a cache file, so it can be loaded with
+    /// [`WebrootInfo::load_cache`] as long as the webroot does not change.
+    pub fn save_cache(&self, cache: &Path, root: &Path, config: &ExtractorConfig) -> Result<()> {
+        let cached = CachedWebrootInfo {
+            key: cache_key(root, cache, config)?,
+            font_stacks: self
+                .font_stacks
+                .iter()
+                .map(|stack| CachedFontStack {
+                    stack: stack.stack.iter().map(|x| x.to_string()).collect(),
+                    samples: stack
+                        .samples
+                        .iter()
+                        .map(|sample| CachedTextSample {
+                            styles: sample.used_styles.iter().collect(),
+                            weights: sample.used_weights.iter().map(|x| x.as_num()).collect(),
+                            content: sample.content.iter().map(|x| x.to_string()).collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            font_face_ranges: self
+                .font_face_ranges
+                .iter()
+                .map(|(family, range)| (family.to_string(), range.compressed()))
+                .collect(),
+            targets: self.targets.to_cached(),
+        };
+        let data = bincode::encode_to_vec(&cached, standard())?;
+        std::fs::write(cache, zstd_compress(&data)?)?;
+        Ok(())
+    }
+}
@@ -1,21 +1,32 @@
 use crate::{
     apply_rules::{ParsedFontStyle, ResolvedNodeProperties},
+    gather_css::{FontFaceRange, GenericFamilies, ParsedCssRule, RawCssRule},
     rewrite_css::RewriteTargets,
+    ExtractorConfig, ExtractorFlags,
 };
 use anyhow::Result;
 use arcstr::ArcStr;
 use enumset::EnumSet;
-use mkwebfont_common::hashing::WyHashBuilder;
+use mkwebfont_common::{
+    character_set::CharacterSet,
+    hashing::{WyHashBuilder, WyHashMap},
+};
 use mkwebfont_fontops::font_info::{FontStyle, FontWeight};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::Arc,
 };
 
 #[derive(Debug, Clone)]
 pub struct WebrootInfo {
     pub font_stacks: Vec<FontStackInfo>,
+    /// The characters covered by the `@font-face` rules for each font family in the webroot's
+    /// CSS, for families where every such rule has a `unicode-range`.
+    pub font_face_ranges: WyHashMap<ArcStr, CharacterSet>,
     pub(crate) targets: RewriteTargets,
+    /// The generic family mappings the webroot was extracted with, used to match its font stacks
+    /// when rewriting it.
+    pub(crate) generic_families: GenericFamilies,
 }
 
 #[derive(Debug, Clone)]
@@ -65,6 +76,9 @@ pub struct TextInfoBuilder {
     cached_strs: HashSet<ArcStr, WyHashBuilder>,
     cached_stacks: HashSet<Arc<[ArcStr]>, WyHashBuilder>,
     cached_weights: HashSet<Arc<[FontWeight]>, WyHashBuilder>,
+    all_content: HashSet<ArcStr, WyHashBuilder>,
+    content_stacks: HashSet<(Arc<[ArcStr]>, TextSampleKey), WyHashBuilder>,
+    font_face_ranges: HashMap<ArcStr, Option<CharacterSet>, WyHashBuilder>,
 }
 impl TextInfoBuilder {
     fn intern_str(&mut self, str: &str) -> ArcStr {
@@ -102,12 +116,8 @@ impl TextInfoBuilder {
         }
     }
 
-    pub fn push_sample(
-        &mut self,
-        properties: &ResolvedNodeProperties,
-        additional_text: &[ArcStr],
-    ) -> Vec<Arc<[ArcStr]>> {
-        let key = TextSampleKey {
+    fn sample_key(&mut self, properties: &ResolvedNodeProperties) -> TextSampleKey {
+        TextSampleKey {
             styles: properties
                 .font_style
                 .iter()
@@ -118,7 +128,45 @@ impl TextInfoBuilder {
                 })
                 .collect(),
             weights: self.intern_weights(&properties.font_weight),
-        };
+        }
+    }
+
+    /// Records the `content` of every pseudo-element rule, whether or not it matches anything.
+    pub fn push_content_rules(&mut self, rules: &[Arc<RawCssRule>]) {
+        for rule in rules {
+            if rule.pseudo_element.is_some() {
+                if let ParsedCssRule::Override(content) = &rule.declarations.content {
+                    if !content.is_empty() {
+                        let content = self.intern_str(content);
+                        self.all_content.insert(content);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the coverage declared by `@font-face` rules. A family is only considered limited to
+    /// a `unicode-range` if every `@font-face` rule for it has one.
+    pub fn push_font_faces(&mut self, faces: &[FontFaceRange]) {
+        for face in faces {
+            match self.font_face_ranges.entry(face.family.clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(face.range.clone());
+                }
+                Entry::Occupied(mut entry) => match (entry.get_mut(), &face.range) {
+                    (Some(current), Some(range)) => *current |= range,
+                    (current, _) => *current = None,
+                },
+            }
+        }
+    }
+
+    pub fn push_sample(
+        &mut self,
+        properties: &ResolvedNodeProperties,
+        additional_text: &[ArcStr],
+    ) -> Vec<Arc<[ArcStr]>> {
+        let key = self.sample_key(properties);
         let content: Vec<_> = additional_text
             .iter()
             .chain(additional_text.iter())
@@ -130,6 +178,9 @@ impl TextInfoBuilder {
         for stack in &properties.font_stack {
             let stack = self.intern_stack(stack);
             result.push(stack.clone());
+            if !properties.content.is_empty() {
+                self.content_stacks.insert((stack.clone(), key.clone()));
+            }
             let texts = self
                 .stacks
                 .entry(stack)
@@ -141,23 +192,47 @@ impl TextInfoBuilder {
         result
     }
 
-    pub fn build(&self, targets: &RewriteTargets) -> WebrootInfo {
+    /// Builds the final webroot information.
+    ///
+    /// If [`ExtractorFlags::KeepAllContent`] is set, every font stack used to display `content` is
+    /// assumed to be able to display the `content` of any pseudo-element rule on the site.
+    pub fn build(&self, targets: &RewriteTargets, config: &ExtractorConfig) -> WebrootInfo {
+        let keep_all_content = config.flags.contains(ExtractorFlags::KeepAllContent);
         let mut keys: Vec<_> = self.stacks.keys().collect();
         keys.sort();
 
-        let mut out = WebrootInfo { font_stacks: vec![], targets: targets.clone() };
+        let font_face_ranges = self
+            .font_face_ranges
+            .iter()
+            .filter_map(|(family, range)| Some((family.clone(), range.clone()?)))
+            .collect();
+        let mut out = WebrootInfo {
+            font_stacks: vec![],
+            font_face_ranges,
+            targets: targets.clone(),
+            generic_families: config.generic_families.clone(),
+        };
         for key in keys {
             let stack = self.stacks.get(key).unwrap();
             let mut stack_keys: Vec<_> = stack.keys().collect();
             stack_keys.sort();
 
             let mut stack_info = FontStackInfo { stack: key.clone(), samples: vec![] };
-            for key in stack_keys {
-                let mut content: Vec<_> = stack.get(key).unwrap().into_iter().cloned().collect();
+            for stack_key in stack_keys {
+                let texts = stack.get(stack_key).unwrap();
+                let mut content: Vec<_> = if keep_all_content
+                    && self
+                        .content_stacks
+                        .contains(&(key.clone(), stack_key.clone()))
+                {
+                    texts.union(&self.all_content).cloned().collect()
+                } else {
+                    texts.iter().cloned().collect()
+                };
                 content.sort();
                 stack_info.samples.push(TextSample {
-                    used_styles: key.styles,
-                    used_weights: key.weights.clone(),
+                    used_styles: stack_key.styles,
+                    used_weights: stack_key.weights.clone(),
                     content,
                 });
             }
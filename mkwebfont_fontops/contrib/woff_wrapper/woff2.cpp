@@ -1,4 +1,6 @@
+#include <woff2/decode.h>
 #include <woff2/encode.h>
+#include <woff2/output.h>
 
 #ifdef __cplusplus
 extern "C" {
@@ -32,6 +34,24 @@ size_t ComputeTTFToWOFF2Size(
     return woff2::MaxWOFF2CompressedSize(data, length, extended_metadata);
 }
 
+size_t ComputeWOFF2ToTTFSize(const uint8_t *data, size_t length) {
+    return woff2::ComputeWOFF2FinalSize(data, length);
+}
+
+int ConvertWOFF2ToTTF(
+    const uint8_t *data,
+    size_t length,
+    uint8_t *result,
+    size_t *result_length
+) {
+    woff2::WOFF2MemoryOut out(result, *result_length);
+    if (!woff2::ConvertWOFF2ToTTF(data, length, &out)) {
+        return 0;
+    }
+    *result_length = out.Size();
+    return 1;
+}
+
 #ifdef __cplusplus
 }
 #endif
\ No newline at end of file
@@ -0,0 +1,103 @@
+use hb_subset::{
+    sys::{hb_blob_destroy, hb_blob_get_data, hb_face_reference_table},
+    FontFace, Tag,
+};
+
+/// `fsType` bit: the font may not be embedded without the legal owner's permission.
+const FS_TYPE_RESTRICTED: u16 = 0x0002;
+/// `fsType` bit: the font may not be subset before embedding.
+const FS_TYPE_NO_SUBSETTING: u16 = 0x0100;
+/// `fsType` bit: only bitmaps in the font may be embedded.
+const FS_TYPE_BITMAP_ONLY: u16 = 0x0200;
+
+/// Information used to classify a font, taken from its `OS/2` and `post` tables.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FontClassification {
+    /// The `usWeightClass` of the font, if it is within the range of valid CSS weights.
+    pub weight_class: Option<u32>,
+    /// The `usWidthClass` of the font, from 1 (ultra-condensed) to 9 (ultra-expanded), with 5
+    /// being normal width.
+    pub width_class: Option<u32>,
+    /// The embedding permissions (`fsType`) of the font. This is 0 (installable embedding) if the
+    /// font has no `OS/2` table.
+    pub fs_type: u16,
+    /// Whether every glyph in the font has the same advance width.
+    pub is_monospace: bool,
+    /// Whether the font has serifs, or `None` if the font does not say.
+    pub is_serif: Option<bool>,
+}
+impl FontClassification {
+    /// Returns whether the `fsType` of the font allows embedding it in documents, such as web
+    /// pages.
+    pub fn embeddable(&self) -> bool {
+        self.fs_type & (FS_TYPE_RESTRICTED | FS_TYPE_BITMAP_ONLY) == 0
+    }
+
+    /// Returns whether the `fsType` of the font allows it to be subset.
+    pub fn allows_subsetting(&self) -> bool {
+        self.fs_type & FS_TYPE_NO_SUBSETTING == 0
+    }
+}
+
+/// Calls a function with the contents of a table in the font, or an empty slice if it is missing.
+fn with_table<R>(face: &FontFace, tag: &[u8; 4], func: impl FnOnce(&[u8]) -> R) -> R {
+    unsafe {
+        let blob = hb_face_reference_table(face.as_raw(), Tag::new(tag).into());
+        let mut len = 0;
+        let data = hb_blob_get_data(blob, &mut len);
+        let result = if data.is_null() {
+            func(&[])
+        } else {
+            func(std::slice::from_raw_parts(data as *const u8, len as usize))
+        };
+        hb_blob_destroy(blob);
+        result
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+pub fn get_font_classification(face: &FontFace) -> FontClassification {
+    let mut info = FontClassification::default();
+    with_table(face, b"OS/2", |os2| {
+        info.weight_class = read_u16(os2, 4)
+            .map(|x| x as u32)
+            .filter(|x| (1..=1000).contains(x));
+        info.width_class = read_u16(os2, 6)
+            .map(|x| x as u32)
+            .filter(|x| (1..=9).contains(x));
+        info.fs_type = read_u16(os2, 8).unwrap_or(0);
+
+        // the PANOSE classification is only meaningful for Latin text fonts (family type 2)
+        let family_class = os2.get(30).copied();
+        let panose = os2.get(32..42).filter(|x| x[0] == 2);
+        if let Some(panose) = panose {
+            info.is_monospace = panose[3] == 9;
+            info.is_serif = match panose[1] {
+                2..=10 => Some(true),
+                11..=15 => Some(false),
+                _ => None,
+            };
+        }
+        if info.is_serif.is_none() {
+            // the high byte of `sFamilyClass` gives the IBM font class
+            info.is_serif = match family_class {
+                Some(1..=5 | 7) => Some(true),
+                Some(8) => Some(false),
+                _ => None,
+            };
+        }
+    });
+    with_table(face, b"post", |post| {
+        if read_u32(post, 12).is_some_and(|x| x != 0) {
+            info.is_monospace = true;
+        }
+    });
+    info
+}
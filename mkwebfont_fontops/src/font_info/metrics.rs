@@ -0,0 +1,57 @@
+use hb_subset::{
+    sys::{
+        hb_face_get_upem, hb_font_create, hb_font_destroy, hb_font_extents_t, hb_font_get_h_extents,
+    },
+    FontFace,
+};
+
+/// The vertical metrics of a font, as percentages of the em size.
+///
+/// These are taken from the original font, so that every subset generated from it can be given
+/// identical metrics in CSS.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+pub fn get_font_metrics(face: &FontFace) -> Option<FontMetrics> {
+    unsafe {
+        let face = face.as_raw();
+
+        let upem = hb_face_get_upem(face);
+        if upem == 0 {
+            return None;
+        }
+
+        let font = hb_font_create(face);
+        let mut extents = hb_font_extents_t {
+            ascender: 0,
+            descender: 0,
+            line_gap: 0,
+            reserved9: 0,
+            reserved8: 0,
+            reserved7: 0,
+            reserved6: 0,
+            reserved5: 0,
+            reserved4: 0,
+            reserved3: 0,
+            reserved2: 0,
+            reserved1: 0,
+        };
+        let success = hb_font_get_h_extents(font, &mut extents) != 0;
+        hb_font_destroy(font);
+
+        if success {
+            let upem = upem as f32;
+            Some(FontMetrics {
+                ascent: extents.ascender as f32 / upem * 100.0,
+                descent: extents.descender.unsigned_abs() as f32 / upem * 100.0,
+                line_gap: extents.line_gap as f32 / upem * 100.0,
+            })
+        } else {
+            None
+        }
+    }
+}
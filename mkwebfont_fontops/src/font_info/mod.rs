@@ -1,9 +1,10 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use bincode::{Decode, Encode};
 use enumset::{EnumSet, EnumSetType};
-use hb_subset::{Blob, FontFace, SubsetInput};
+use hb_subset::{Blob, FontFace, SubsetInput, Tag};
 use mkwebfont_common::{character_set::CharacterSet, hashing::WyHashBuilder};
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
     fmt::{Debug, Display, Formatter},
     ops::RangeInclusive,
@@ -12,12 +13,18 @@ use std::{
         Arc,
     },
 };
-use tracing::debug;
+use tracing::{debug, warn};
+use unicode_properties::{GeneralCategory, UnicodeGeneralCategory};
 
+mod classification;
+mod metrics;
 mod variation_axises;
+mod woff;
 mod woff2;
 
-pub use variation_axises::{AxisName, VariationAxis};
+pub use classification::FontClassification;
+pub use metrics::FontMetrics;
+pub use variation_axises::{parse_axis_name, AxisName, VariationAxis};
 
 #[derive(EnumSetType, Debug, Decode, Encode)]
 pub enum FontStyle {
@@ -60,9 +67,58 @@ pub enum FontWeight {
     Bold,
     Numeric(u32),
 }
+
+/// The number of codepoints a single glyph may be mapped to before they are considered a
+/// many-to-one mapping.
+const MANY_TO_ONE_LIMIT: usize = 128;
+
+/// Weights for uncommon weight names (such as `Book`) in style strings, checked after the
+/// standard names.
+#[derive(Clone, Debug)]
+pub struct WeightNames(Vec<(String, u32)>);
+impl WeightNames {
+    /// Sets the weight for a custom weight name, replacing the existing weight if the name is
+    /// already known.
+    pub fn insert(&mut self, name: &str, weight: u32) {
+        let name = name.to_lowercase();
+        if let Some(entry) = self.0.iter_mut().find(|x| x.0 == name) {
+            entry.1 = weight;
+        } else {
+            self.0.push((name, weight));
+        }
+    }
+
+    /// Returns the weight for the first custom weight name found among the words of a lowercase
+    /// style string.
+    fn find(&self, style: &str) -> Option<u32> {
+        self.0
+            .iter()
+            .find(|(name, _)| style.split_whitespace().any(|word| word == name))
+            .map(|(_, weight)| *weight)
+    }
+
+    /// Returns the custom weight names and their weights.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.0.iter().map(|(name, weight)| (name.as_str(), *weight))
+    }
+}
+impl Default for WeightNames {
+    fn default() -> Self {
+        WeightNames(vec![("book".to_string(), 380), ("text".to_string(), 400)])
+    }
+}
+
 impl FontWeight {
-    pub fn infer(style: &str) -> FontWeight {
+    pub fn infer(style: &str, names: &WeightNames) -> FontWeight {
         let style = style.to_lowercase().replace("-", " ");
+        if let Some(num) = style
+            .split_whitespace()
+            .next()
+            .and_then(|x| x.parse::<u32>().ok())
+            .filter(|x| (1..=1000).contains(x))
+        {
+            return FontWeight::from_num(num);
+        }
         match style {
             x if x.contains("thin") || x.contains("hairline") => FontWeight::Numeric(100),
             x if x.contains("extralight") || x.contains("extra light") => FontWeight::Numeric(200),
@@ -79,7 +135,10 @@ impl FontWeight {
             x if x.contains("heavy") => FontWeight::Numeric(900),
             x if x.contains("extrablack") || x.contains("extra black") => FontWeight::Numeric(950),
             x if x.contains("ultrablack") || x.contains("ultra black") => FontWeight::Numeric(950),
-            _ => FontWeight::Regular,
+            x => names
+                .find(&x)
+                .map(FontWeight::from_num)
+                .unwrap_or(FontWeight::Regular),
         }
     }
 
@@ -144,16 +203,37 @@ impl FontId {
     }
 }
 
+/// The names a font's weight is inferred from, when it is not a variable font and has no OS/2
+/// weight class.
+#[derive(Clone)]
+struct WeightNameSource {
+    style: String,
+    family_suffix: Option<String>,
+}
+impl WeightNameSource {
+    fn infer(&self, names: &WeightNames) -> FontWeight {
+        let style = FontWeight::infer(&self.style, names);
+        match &self.family_suffix {
+            Some(suffix) if style == FontWeight::Regular => FontWeight::infer(suffix, names),
+            _ => style,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct FontFaceWrapper(Arc<FontFaceData>);
+#[derive(Clone)]
 struct FontFaceData {
     font_id: FontId,
     font_family: String,
     font_style: String,
     font_version: String,
     variations: Vec<VariationAxis>,
+    metrics: Option<FontMetrics>,
+    classification: FontClassification,
     parsed_font_style: FontStyle,
     parsed_font_weight: FontWeight,
+    weight_name_source: Option<WeightNameSource>,
     available_codepoints: CharacterSet,
     font_data: Arc<[u8]>,
     font_index: u32,
@@ -166,13 +246,21 @@ impl FontFaceWrapper {
     ) -> Result<Vec<FontFaceWrapper>> {
         let buffer: Arc<[u8]> = buffer.into();
 
+        // woff/woff2 files are decompressed up front, so the subsetter only ever sees sfnt data
         let is_woff = buffer.len() >= 4 && &buffer[0..4] == b"wOFF";
         let is_woff2 = buffer.len() >= 4 && &buffer[0..4] == b"wOF2";
-        let is_collection = buffer.len() >= 4 && &buffer[0..4] == b"ttcf";
+        let buffer: Arc<[u8]> = if is_woff {
+            woff::decompress(&buffer)?.into()
+        } else if is_woff2 {
+            let Some(sfnt) = woff2::decompress(&buffer) else {
+                bail!("Could not decompress .woff2 file.");
+            };
+            sfnt.into()
+        } else {
+            buffer
+        };
 
-        if is_woff || is_woff2 {
-            bail!("woff/woff2 input is not supported. Please convert to .ttf or .otf first.");
-        }
+        let is_collection = buffer.len() >= 4 && &buffer[0..4] == b"ttcf";
 
         let mut fonts = Vec::new();
         if let Some(font) = Self::load_for_font(filename_hint.clone(), buffer.clone(), 0)? {
@@ -206,18 +294,17 @@ impl FontFaceWrapper {
 
         let variations = variation_axises::get_variation_axises(&font_face);
         let is_variable = !variations.is_empty();
+        let metrics = metrics::get_font_metrics(&font_face);
+        let classification = classification::get_font_classification(&font_face);
 
-        let (font_family, weight_from_name) = {
+        let (font_family, family_suffix) = {
             // a lot of dynamic fonts have a weight prebaked in the font_family for some reason
             let family = font_face.font_family();
             let typographic_family = font_face.typographic_family();
 
             if family.starts_with(&typographic_family) && !typographic_family.is_empty() {
                 let remaining = family.strip_prefix(&typographic_family);
-                (typographic_family, match remaining {
-                    None => None,
-                    Some(family) => Some(FontWeight::infer(family.trim())),
-                })
+                (typographic_family, remaining.map(|x| x.trim().to_string()))
             } else {
                 (family, None)
             }
@@ -231,21 +318,30 @@ impl FontFaceWrapper {
             .trim()
             .to_string();
         let parsed_font_style = FontStyle::infer(&font_style);
+        let weight_name_source = if is_variable || classification.weight_class.is_some() {
+            None
+        } else {
+            Some(WeightNameSource { style: font_style.clone(), family_suffix })
+        };
         let parsed_font_weight = if is_variable {
             FontWeight::Regular // font weight doesn't matter for variable fonts
+        } else if let Some(weight) = classification.weight_class {
+            // the OS/2 weight class is authoritative when present, names are only a fallback
+            FontWeight::from_num(weight)
         } else {
-            let style = FontWeight::infer(&font_style);
-            if style == FontWeight::Regular {
-                weight_from_name.unwrap_or(style)
-            } else {
-                style
-            }
+            weight_name_source
+                .as_ref()
+                .unwrap()
+                .infer(&WeightNames::default())
         };
 
         let mut available_codepoints = CharacterSet::new();
         for char in &font_face.covered_codepoints()? {
             available_codepoints.insert(char as u32);
         }
+        if available_codepoints.len() > font_face.glyph_count() * 4 {
+            Self::remove_many_to_one_mappings(&font_face, &font_family, &mut available_codepoints)?;
+        }
 
         debug!(
             "Loaded font: {font_family} / {font_style} / {font_version} / {} gylphs{}",
@@ -285,8 +381,11 @@ impl FontFaceWrapper {
             font_style,
             font_version,
             variations,
+            metrics,
+            classification,
             parsed_font_style,
             parsed_font_weight,
+            weight_name_source,
             available_codepoints,
             font_data,
             font_index: idx,
@@ -294,6 +393,37 @@ impl FontFaceWrapper {
         }))))
     }
 
+    /// Removes codepoints mapped to glyphs that many codepoints share, such as those mapped by
+    /// `cmap` format 13 in last-resort fonts. Treating these as covered would mean subsetting a
+    /// huge number of codepoints for no benefit.
+    fn remove_many_to_one_mappings(
+        font_face: &FontFace,
+        font_family: &str,
+        codepoints: &mut CharacterSet,
+    ) -> Result<()> {
+        let mapping = font_face.nominal_glyph_mapping()?;
+        let mut glyph_uses: HashMap<u32, usize> = HashMap::new();
+        for (_, glyph) in &mapping {
+            *glyph_uses.entry(glyph).or_default() += 1;
+        }
+
+        let mut removed = CharacterSet::new();
+        for (ch, glyph) in &mapping {
+            if glyph_uses[&glyph] > MANY_TO_ONE_LIMIT {
+                removed.insert(ch as u32);
+            }
+        }
+        if !removed.is_empty() {
+            warn!(
+                "{font_family} maps {} codepoints to shared glyphs (such as with a many-to-one \
+                 `cmap`). These codepoints will be ignored.",
+                removed.len(),
+            );
+            *codepoints = codepoints.clone() - &removed;
+        }
+        Ok(())
+    }
+
     pub fn codepoints_in_set(&self, set: &CharacterSet) -> CharacterSet {
         self.0.available_codepoints.clone() & set
     }
@@ -318,6 +448,12 @@ impl FontFaceWrapper {
     pub fn variations(&self) -> &[VariationAxis] {
         &self.0.variations
     }
+    pub fn metrics(&self) -> Option<FontMetrics> {
+        self.0.metrics
+    }
+    pub fn classification(&self) -> FontClassification {
+        self.0.classification
+    }
     pub fn parsed_font_style(&self) -> FontStyle {
         self.0.parsed_font_style
     }
@@ -325,6 +461,21 @@ impl FontFaceWrapper {
         self.0.parsed_font_weight
     }
 
+    /// Returns this font with its weight inferred again using the given custom weight names, if
+    /// the weight was inferred from the font's names.
+    pub fn with_weight_names(&self, names: &WeightNames) -> FontFaceWrapper {
+        let Some(source) = &self.0.weight_name_source else {
+            return self.clone();
+        };
+        let weight = source.infer(names);
+        if weight == self.0.parsed_font_weight {
+            return self.clone();
+        }
+        let mut data = (*self.0).clone();
+        data.parsed_font_weight = weight;
+        FontFaceWrapper(Arc::new(data))
+    }
+
     pub fn font_data(&self) -> &[u8] {
         &self.0.font_data
     }
@@ -342,7 +493,53 @@ impl FontFaceWrapper {
         }
     }
 
-    pub fn subset(&self, name: &str, chars: &CharacterSet) -> Result<Vec<u8>> {
+    /// Returns whether the font is normal width, or can be with its width axis. Fonts that do not
+    /// declare a width are assumed to be normal width.
+    pub fn is_normal_width(&self) -> bool {
+        match self.axis_range(AxisName::Width) {
+            Some(range) => range.contains(&100.0),
+            None => self.0.classification.width_class.is_none_or(|x| x == 5),
+        }
+    }
+
+    /// Returns the range of a non-hidden variation axis, if the font has one.
+    pub fn axis_range(&self, axis: AxisName) -> Option<RangeInclusive<f32>> {
+        self.variations()
+            .iter()
+            .find(|x| !x.is_hidden && x.axis == Some(axis))
+            .map(|x| x.range.clone())
+    }
+
+    pub fn subset(
+        &self,
+        name: &str,
+        chars: &CharacterSet,
+        settings: &SubsetSettings,
+    ) -> Result<Vec<u8>> {
+        let sfnt = self.subset_sfnt(chars, settings)?;
+        Ok(woff2::compress(&sfnt, name.to_string(), 11, true).unwrap())
+    }
+
+    /// Subsets the font, returning the subset as a .woff2 file, along with a .woff file if
+    /// [`SubsetSettings::emit_woff1`] is set.
+    pub fn subset_woff(
+        &self,
+        name: &str,
+        chars: &CharacterSet,
+        settings: &SubsetSettings,
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>)> {
+        let sfnt = self.subset_sfnt(chars, settings)?;
+        let woff2 = woff2::compress(&sfnt, name.to_string(), 11, true).unwrap();
+        let woff = if settings.emit_woff1 {
+            Some(woff::compress(&sfnt)?)
+        } else {
+            None
+        };
+        Ok((woff2, woff))
+    }
+
+    /// Subsets the font, returning the subset as uncompressed sfnt data.
+    fn subset_sfnt(&self, chars: &CharacterSet, settings: &SubsetSettings) -> Result<Vec<u8>> {
         // Load the font into harfbuzz
         let blob = Blob::from_bytes(&self.0.font_data)?;
         let mut font = FontFace::new_with_index(blob, self.0.font_index)?;
@@ -350,21 +547,41 @@ impl FontFaceWrapper {
         // Prepare the subsetting plan
         let mut subset_input = SubsetInput::new()?;
         subset_input.unicode_set().clear();
-        for ch in chars {
-            let ch = char::from_u32(ch).unwrap();
+        for ch in chars.chars() {
             subset_input.unicode_set().insert(ch);
         }
         for variation in &self.0.variations {
-            // TODO: Do not hardcode allowed axises
-            if variation.is_hidden || variation.axis != Some(AxisName::Weight) {
+            if variation.is_hidden || !settings.keeps_axis(variation.axis) {
                 variation.pin(&mut font, &mut subset_input);
             }
         }
+        if let Some(scripts) = &settings.layout_scripts {
+            // the default script is always kept, as it is used for text in any other script
+            let mut set = subset_input.layout_script_tag_set();
+            set.clear();
+            set.insert(Tag::new(b"DFLT"));
+            for script in &**scripts {
+                set.insert(Tag::new(script));
+            }
+        }
+        if settings.retain_notdef_outline {
+            subset_input.flags().retain_notdef_outline();
+        }
+        if settings.strip_hinting {
+            subset_input.flags().remove_hinting();
+        }
+        if settings.prune_mark_positioning && !chars.chars().any(is_mark) {
+            // mark positioning rules are dead weight in subsets without any combining marks
+            let mut set = subset_input.layout_feature_tag_set();
+            for feature in MARK_FEATURES {
+                set.remove(Tag::new(*feature));
+            }
+        }
 
         // Subset the font
         let new_font = subset_input.subset_font(&font)?;
         let new_font = new_font.underlying_blob().to_vec();
-        Ok(woff2::compress(&new_font, name.to_string(), 11, true).unwrap())
+        Ok(new_font)
     }
 }
 impl Debug for FontFaceWrapper {
@@ -384,6 +601,85 @@ impl Display for FontFaceWrapper {
     }
 }
 
+/// Recompresses a .woff2 file at a given Brotli quality (0 to 11).
+pub fn recompress_woff2(data: &[u8], quality: usize) -> Result<Vec<u8>> {
+    ensure!(quality <= 11, "WOFF2 quality must be between 0 and 11.");
+    let Some(sfnt) = woff2::decompress(data) else {
+        bail!("Could not decompress .woff2 file.");
+    };
+    let Some(woff2) = woff2::compress(&sfnt, String::new(), quality, true) else {
+        bail!("Could not compress .woff2 file.");
+    };
+    Ok(woff2)
+}
+
+/// Settings controlling how a font is subset.
+#[derive(Clone, Debug, Default)]
+pub struct SubsetSettings {
+    /// Keeps every non-hidden variation axis variable, rather than only the weight axis.
+    pub preserve_all_axes: bool,
+    /// The variation axes kept variable in the subset. Other axes are pinned to their default
+    /// value. If this is `None`, only the weight axis is kept.
+    pub kept_axes: Option<Arc<[AxisName]>>,
+    /// The template used for the file names of subsets. See
+    /// [`validate_file_name_template`](crate::subsetter::validate_file_name_template).
+    pub file_name_template: Option<Arc<str>>,
+    /// The OpenType script tags whose layout rules are kept in the subset. If this is `None`, the
+    /// layout rules for all scripts are kept. See [`parse_script_tag`].
+    pub layout_scripts: Option<Arc<[[u8; 4]]>>,
+    /// Places the files for each font family in a subdirectory of the store named after it.
+    pub nested_store: bool,
+    /// Removes the `GPOS` mark positioning features from subsets that contain no combining marks.
+    pub prune_mark_positioning: bool,
+    /// Keeps the outline of the `.notdef` glyph, which is otherwise replaced with an empty glyph.
+    pub retain_notdef_outline: bool,
+    /// Removes hinting instructions and the tables used only by them.
+    pub strip_hinting: bool,
+    /// Generates a .woff file for every subset alongside the .woff2 file, for browsers that do
+    /// not support WOFF 2.0.
+    pub emit_woff1: bool,
+    /// Derives the hash in the file name of each subset from its own contents only, rather than
+    /// from the contents of all subsets of the font.
+    pub per_subset_hashing: bool,
+}
+
+impl SubsetSettings {
+    /// Returns whether a non-hidden variation axis is kept variable in the subset.
+    pub fn keeps_axis(&self, axis: Option<AxisName>) -> bool {
+        if self.preserve_all_axes {
+            return true;
+        }
+        match (axis, &self.kept_axes) {
+            (Some(axis), Some(kept)) => kept.contains(&axis),
+            (Some(axis), None) => axis == AxisName::Weight,
+            (None, _) => false,
+        }
+    }
+}
+
+/// The layout features used to position combining marks.
+const MARK_FEATURES: &[&[u8; 4]] = &[b"mark", b"mkmk", b"abvm", b"blwm"];
+
+fn is_mark(ch: char) -> bool {
+    matches!(
+        ch.general_category(),
+        GeneralCategory::NonspacingMark
+            | GeneralCategory::SpacingMark
+            | GeneralCategory::EnclosingMark
+    )
+}
+
+/// Parses an OpenType script tag such as `latn` or `dev2`, padding it with spaces if needed.
+pub fn parse_script_tag(tag: &str) -> Result<[u8; 4]> {
+    ensure!(
+        !tag.is_empty() && tag.len() <= 4 && tag.bytes().all(|x| x.is_ascii_alphanumeric()),
+        "Invalid OpenType script tag: {tag:?}"
+    );
+    let mut out = *b"    ";
+    out[..tag.len()].copy_from_slice(tag.as_bytes());
+    Ok(out)
+}
+
 #[derive(Clone, Debug)]
 pub struct FontFaceSet {
     list: Vec<FontFaceWrapper>,
@@ -426,7 +722,24 @@ impl FontFaceSet {
         if resolve.len() == 1 {
             Ok(&resolve[0])
         } else {
-            bail!("Font name {name:?} is ambigious!");
+            let mut candidates = String::new();
+            for font in resolve {
+                let weights = font.weight_range();
+                let weights = if weights.start() == weights.end() {
+                    weights.start().to_string()
+                } else {
+                    format!("{}-{}", weights.start(), weights.end())
+                };
+                candidates.push_str(&format!(
+                    "\n * {font} (family: {:?}, style: {}, weight: {weights})",
+                    font.font_family(),
+                    font.parsed_font_style(),
+                ));
+            }
+            bail!(
+                "Font name {name:?} is ambiguous! Use a \"Family Style\" name to select one of the \
+                 matching fonts:{candidates}"
+            );
         }
     }
 
@@ -462,7 +775,8 @@ impl FontFaceSet {
         if let Some(font) = near_match
             .iter()
             .map(|x| {
-                (x, (x.parsed_font_style() == style, weight.dist_from_range(&x.weight_range())))
+                let dist = weight.dist_from_range(&x.weight_range());
+                (x, (x.parsed_font_style() == style, Reverse(dist)))
             })
             .max_by_key(|x| x.1)
             .map(|x| *x.0)
@@ -473,6 +787,42 @@ impl FontFaceSet {
         }
     }
 
+    /// Returns every font in a family that matches a style and weight equally well.
+    ///
+    /// As `font-stretch` is not taken from the CSS, normal width faces are preferred over
+    /// condensed or expanded ones, as they are what browsers select by default. Unlike
+    /// [`FontFaceSet::resolve_by_style`], this does not pick a single font when several faces
+    /// still match equally well.
+    pub fn resolve_all_by_style(
+        &self,
+        name: &str,
+        style: FontStyle,
+        weight: FontWeight,
+    ) -> Result<Vec<&FontFaceWrapper>> {
+        let candidates: Vec<_> = self
+            .list
+            .iter()
+            .filter(|x| {
+                x.font_family().eq_ignore_ascii_case(name)
+                    && x.parsed_font_style().is_compatible(style)
+            })
+            .map(|x| {
+                let dist = weight.dist_from_range(&x.weight_range());
+                (x, (x.parsed_font_style() == style, Reverse(dist), x.is_normal_width()))
+            })
+            .collect();
+        let Some(best) = candidates.iter().map(|x| x.1).max() else {
+            bail!("No fonts match specification: {name} / {style} / {weight}");
+        };
+        Ok(candidates
+            .into_iter()
+            .filter(|x| x.1 == best)
+            .map(|x| x.0)
+            .collect())
+    }
+
+    /// Returns every font in a family needed to display text in any of the given styles and
+    /// weights. Faces that match equally well are all included.
     pub fn resolve_by_styles(
         &self,
         name: &str,
@@ -482,7 +832,9 @@ impl FontFaceSet {
         let mut ids: HashSet<_, WyHashBuilder> = HashSet::default();
         for style in styles {
             for weight in weights {
-                ids.insert(self.resolve_by_style(name, style, *weight)?.font_id());
+                for font in self.resolve_all_by_style(name, style, *weight)? {
+                    ids.insert(font.font_id());
+                }
             }
         }
 
@@ -491,3 +843,50 @@ impl FontFaceSet {
         Ok(ids.into_iter().flat_map(|x| self.get_by_id(x)).collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::NOTO_SANS;
+
+    /// Returns a copy of a font with a field in its `OS/2` table replaced.
+    fn with_os2_field(font: &[u8], field_offset: usize, value: u16) -> Vec<u8> {
+        let mut font = font.to_vec();
+        let num_tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+        let os2 = (0..num_tables)
+            .map(|i| 12 + i * 16)
+            .find(|&record| &font[record..record + 4] == b"OS/2")
+            .expect("font has no OS/2 table");
+        let offset = u32::from_be_bytes(font[os2 + 8..os2 + 12].try_into().unwrap()) as usize;
+        let field = offset + field_offset;
+        font[field..field + 2].copy_from_slice(&value.to_be_bytes());
+        font
+    }
+
+    fn load_with_os2_field(field_offset: usize, value: u16) -> FontFaceWrapper {
+        let data = with_os2_field(&std::fs::read(NOTO_SANS).unwrap(), field_offset, value);
+        FontFaceWrapper::load(None, data).unwrap().remove(0)
+    }
+
+    #[test]
+    fn weight_class_overrides_style_name() {
+        let font = load_with_os2_field(4, 500);
+        assert_eq!(font.font_style(), "Regular");
+        assert_eq!(font.parsed_font_weight(), FontWeight::Numeric(500));
+    }
+
+    #[test]
+    fn normal_width_face_is_preferred() {
+        let condensed = load_with_os2_field(6, 3);
+        let normal = load_with_os2_field(6, 5);
+        assert!(!condensed.is_normal_width());
+        assert!(normal.is_normal_width());
+
+        let set = FontFaceSet::build([condensed, normal.clone()].into_iter());
+        let resolved = set
+            .resolve_all_by_style("Noto Sans", FontStyle::Regular, FontWeight::Regular)
+            .unwrap();
+        let ids: Vec<_> = resolved.iter().map(|x| x.font_id()).collect();
+        assert_eq!(ids, [normal.font_id()]);
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::{bail, Result};
 use hb_subset::{
     sys::{
         hb_face_t, hb_ot_name_get_utf8, hb_ot_name_id_t,
@@ -12,18 +13,68 @@ use std::{ffi::c_uint, ops::RangeInclusive};
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum AxisName {
     Weight,
+    Width,
+    Slant,
+    Italic,
+    OpticalSize,
 }
 impl AxisName {
+    const ALL: &'static [AxisName] = &[
+        AxisName::Weight,
+        AxisName::Width,
+        AxisName::Slant,
+        AxisName::Italic,
+        AxisName::OpticalSize,
+    ];
+
     fn of(name: &str) -> Option<AxisName> {
-        match name {
-            x if x.eq_ignore_ascii_case("Weight") => Some(Self::Weight),
-            _ => None,
-        }
+        Self::ALL
+            .iter()
+            .find(|x| name.eq_ignore_ascii_case(x.standard_name()))
+            .copied()
+    }
+
+    fn of_tag(tag: hb_tag_t) -> Option<AxisName> {
+        Self::ALL
+            .iter()
+            .find(|x| u32::from_be_bytes(*x.tag()) == tag)
+            .copied()
     }
 
     pub fn standard_name(&self) -> &'static str {
         match self {
             AxisName::Weight => "Weight",
+            AxisName::Width => "Width",
+            AxisName::Slant => "Slant",
+            AxisName::Italic => "Italic",
+            AxisName::OpticalSize => "Optical Size",
+        }
+    }
+
+    /// Returns the registered OpenType tag of this axis, such as `wght`.
+    pub fn tag(&self) -> &'static [u8; 4] {
+        match self {
+            AxisName::Weight => b"wght",
+            AxisName::Width => b"wdth",
+            AxisName::Slant => b"slnt",
+            AxisName::Italic => b"ital",
+            AxisName::OpticalSize => b"opsz",
+        }
+    }
+}
+
+/// Parses a registered variation axis from its tag (such as `opsz`) or its name (such as
+/// `Optical Size`).
+pub fn parse_axis_name(name: &str) -> Result<AxisName> {
+    let tag = AxisName::ALL.iter().find(|x| name.as_bytes() == x.tag());
+    match tag.copied().or_else(|| AxisName::of(name)) {
+        Some(axis) => Ok(axis),
+        None => {
+            let tags: Vec<_> = AxisName::ALL
+                .iter()
+                .map(|x| String::from_utf8_lossy(x.tag()))
+                .collect();
+            bail!("Unknown variation axis {name:?}. Valid axes: {}", tags.join(", "))
         }
     }
 }
@@ -65,7 +116,7 @@ unsafe fn load_string(face: *mut hb_face_t, name: hb_ot_name_id_t) -> String {
 }
 unsafe fn load_axis_info(face: *mut hb_face_t, axis: hb_ot_var_axis_info_t) -> VariationAxis {
     let mut name = load_string(face, axis.name_id);
-    let axis_name = AxisName::of(&name);
+    let axis_name = AxisName::of_tag(axis.tag).or_else(|| AxisName::of(&name));
     if let Some(axis) = axis_name {
         name = axis.standard_name().to_string();
     }
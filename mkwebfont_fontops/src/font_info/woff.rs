@@ -0,0 +1,141 @@
+//! Conversion between WOFF 1.0 files and plain sfnt data.
+
+use anyhow::{ensure, Context, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+const WOFF_HEADER_LEN: usize = 44;
+const WOFF_TABLE_ENTRY_LEN: usize = 20;
+const SFNT_HEADER_LEN: usize = 12;
+const SFNT_TABLE_RECORD_LEN: usize = 16;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// Decompresses a WOFF file into the sfnt (.ttf or .otf) data it contains.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    ensure!(data.len() >= WOFF_HEADER_LEN && &data[0..4] == b"wOFF", "Not a WOFF file.");
+    let truncated = || "Truncated WOFF file.";
+    let flavor = read_u32(data, 4).with_context(truncated)?;
+    let num_tables = read_u16(data, 12).with_context(truncated)?;
+    ensure!(num_tables > 0, "WOFF file contains no tables.");
+
+    let mut tables = Vec::new();
+    for i in 0..num_tables as usize {
+        let entry = WOFF_HEADER_LEN + i * WOFF_TABLE_ENTRY_LEN;
+        let tag = read_u32(data, entry).with_context(truncated)?;
+        let offset = read_u32(data, entry + 4).with_context(truncated)? as usize;
+        let comp_length = read_u32(data, entry + 8).with_context(truncated)? as usize;
+        let orig_length = read_u32(data, entry + 12).with_context(truncated)? as usize;
+        let checksum = read_u32(data, entry + 16).with_context(truncated)?;
+
+        let raw = data
+            .get(offset..offset + comp_length)
+            .context("Truncated WOFF table data.")?;
+        let table = if comp_length < orig_length {
+            let mut table = Vec::with_capacity(orig_length);
+            ZlibDecoder::new(raw).read_to_end(&mut table)?;
+            ensure!(table.len() == orig_length, "WOFF table has the wrong decompressed length.");
+            table
+        } else {
+            raw.to_vec()
+        };
+        tables.push((tag, checksum, table));
+    }
+
+    // the binary search fields of the sfnt header
+    let entry_selector = num_tables.ilog2() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&num_tables.to_be_bytes());
+    out.extend_from_slice(&search_range.to_be_bytes());
+    out.extend_from_slice(&entry_selector.to_be_bytes());
+    out.extend_from_slice(&range_shift.to_be_bytes());
+
+    let mut offset = SFNT_HEADER_LEN + tables.len() * SFNT_TABLE_RECORD_LEN;
+    for (tag, checksum, table) in &tables {
+        out.extend_from_slice(&tag.to_be_bytes());
+        out.extend_from_slice(&checksum.to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+    for (_, _, table) in &tables {
+        out.extend_from_slice(table);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    Ok(out)
+}
+
+/// Compresses sfnt (.ttf or .otf) data into a WOFF file.
+///
+/// Each table is compressed with zlib, and stored uncompressed if that does not make it smaller.
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let truncated = || "Truncated sfnt data.";
+    let flavor = read_u32(data, 0).with_context(truncated)?;
+    let num_tables = read_u16(data, 4).with_context(truncated)?;
+
+    let mut tables = Vec::new();
+    let mut total_sfnt_size = SFNT_HEADER_LEN + num_tables as usize * SFNT_TABLE_RECORD_LEN;
+    for i in 0..num_tables as usize {
+        let record = SFNT_HEADER_LEN + i * SFNT_TABLE_RECORD_LEN;
+        let tag = read_u32(data, record).with_context(truncated)?;
+        let checksum = read_u32(data, record + 4).with_context(truncated)?;
+        let offset = read_u32(data, record + 8).with_context(truncated)? as usize;
+        let length = read_u32(data, record + 12).with_context(truncated)? as usize;
+
+        let table = data
+            .get(offset..offset + length)
+            .context("Truncated sfnt table data.")?;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(table)?;
+        let compressed = encoder.finish()?;
+        let stored = if compressed.len() < table.len() {
+            compressed
+        } else {
+            table.to_vec()
+        };
+
+        total_sfnt_size += length.next_multiple_of(4);
+        tables.push((tag, checksum, length, stored));
+    }
+    // the WOFF table directory must be sorted by tag
+    tables.sort_by_key(|x| x.0);
+
+    let mut directory = Vec::new();
+    let mut table_data = Vec::new();
+    let data_start = WOFF_HEADER_LEN + tables.len() * WOFF_TABLE_ENTRY_LEN;
+    for (tag, checksum, orig_length, stored) in &tables {
+        directory.extend_from_slice(&tag.to_be_bytes());
+        directory.extend_from_slice(&((data_start + table_data.len()) as u32).to_be_bytes());
+        directory.extend_from_slice(&(stored.len() as u32).to_be_bytes());
+        directory.extend_from_slice(&(*orig_length as u32).to_be_bytes());
+        directory.extend_from_slice(&checksum.to_be_bytes());
+        table_data.extend_from_slice(stored);
+        table_data.resize(table_data.len().next_multiple_of(4), 0);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"wOFF");
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&((data_start + table_data.len()) as u32).to_be_bytes());
+    out.extend_from_slice(&num_tables.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // reserved
+    out.extend_from_slice(&(total_sfnt_size as u32).to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes()); // major version
+    out.extend_from_slice(&0u16.to_be_bytes()); // minor version
+    out.extend_from_slice(&[0; 20]); // no metadata or private data
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&table_data);
+    Ok(out)
+}
@@ -22,6 +22,13 @@ extern "C" {
         brotli_quality: core::ffi::c_int,
         allow_transforms: core::ffi::c_int,
     ) -> core::ffi::c_int;
+    fn ComputeWOFF2ToTTFSize(data: *const u8, length: usize) -> usize;
+    fn ConvertWOFF2ToTTF(
+        data: *const u8,
+        length: usize,
+        result: *mut u8,
+        result_length: *mut usize,
+    ) -> core::ffi::c_int;
 }
 
 /// Compress.
@@ -59,3 +66,23 @@ pub fn compress(data: &[u8], metadata: String, quality: usize, transform: bool)
     result.truncate(result_length);
     result.into()
 }
+
+/// Decompress.
+pub fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let size = unsafe { ComputeWOFF2ToTTFSize(data.as_ptr() as *const _, data.len()) };
+    let mut result = vec![0; size];
+    let mut result_length = result.len();
+    let success = unsafe {
+        ConvertWOFF2ToTTF(
+            data.as_ptr() as *const _,
+            data.len(),
+            result.as_mut_ptr() as *mut _,
+            &mut result_length as *mut _,
+        ) != 0
+    };
+    if !success {
+        return None;
+    }
+    result.truncate(result_length);
+    result.into()
+}
@@ -29,6 +29,15 @@ impl FallbackInfo {
         &*CACHE
     }
 
+    /// Returns every codepoint covered by at least one fallback font.
+    pub fn all_codepoints() -> CharacterSet {
+        let mut chars = CharacterSet::new();
+        for font in &Self::load::<'static>().fonts {
+            chars |= CharacterSet::decompress(&font.codepoints);
+        }
+        chars
+    }
+
     pub fn build_stack(chars: &CharacterSet) -> Vec<String> {
         let mut chars = chars.clone();
         let loaded = Self::load::<'static>();
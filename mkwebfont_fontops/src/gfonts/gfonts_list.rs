@@ -28,6 +28,10 @@ impl GfontsList {
         &*CACHE
     }
 
+    /// Finds a font family by name.
+    ///
+    /// The name is matched case-insensitively, and both the display form (`Noto Sans`) and the
+    /// form used in Google Fonts URLs (`Noto+Sans`) are accepted.
     pub fn find_font(name: &str) -> Option<&'static GfontInfo> {
         static CACHE: LazyLock<HashMap<&'static str, &'static GfontInfo, WyHashBuilder>> =
             LazyLock::new(|| {
@@ -37,10 +41,20 @@ impl GfontsList {
                 }
                 map
             });
-        CACHE.get(name.to_lowercase().as_str()).cloned()
+        CACHE.get(normalize_family_name(name).as_str()).cloned()
     }
 }
 
+/// Normalizes a font family name for lookup, converting `+` to spaces, collapsing whitespace and
+/// lowercasing it.
+fn normalize_family_name(name: &str) -> String {
+    name.split(|c: char| c == '+' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 #[derive(Debug, Clone, Decode, Encode)]
 pub struct GfontInfo {
     pub name: String,
@@ -83,3 +97,16 @@ impl Display for GfontStyleInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn family_name_forms_normalize_equally() {
+        assert_eq!(normalize_family_name("Noto+Sans"), "noto sans");
+        assert_eq!(normalize_family_name("noto sans"), "noto sans");
+        assert_eq!(normalize_family_name("Noto Sans"), "noto sans");
+        assert_eq!(normalize_family_name("  Noto \t+ Sans "), "noto sans");
+    }
+}
@@ -1,10 +1,13 @@
+use anyhow::{bail, ensure, Context, Result};
 use bincode::{config::standard, Decode, Encode};
 use mkwebfont_common::{
     character_set::{CharacterSet, CompressedCharacterSet},
     compression::zstd_decompress,
 };
+use serde::Deserialize;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::Path,
     sync::{Arc, LazyLock},
 };
 
@@ -26,6 +29,90 @@ impl WebfontData {
         });
         &*CACHE
     }
+
+    /// Loads custom subset definitions from a TOML file, or a JSON file if the path ends in
+    /// `.json`.
+    ///
+    /// The file contains a list of subsets, each with a `name`, and its characters given as a
+    /// list of `unicode-range` style `ranges` (such as `U+0000-00FF` or `U+4??`) and/or a string
+    /// of literal `chars`:
+    ///
+    /// ```toml
+    /// [[subset]]
+    /// name = "base-latin"
+    /// ranges = ["U+0020-007E"]
+    ///
+    /// [[subset]]
+    /// name = "logo"
+    /// ranges = ["U+E000-E01F"]
+    /// chars = "™"
+    /// ```
+    ///
+    /// The subsets are kept in the order they are declared.
+    pub fn load_custom(path: &Path) -> Result<WebfontData> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read subset definitions '{}'.", path.display()))?;
+        let custom: CustomSubsets = if path.extension().is_some_and(|x| x == "json") {
+            serde_json::from_str(&contents)?
+        } else {
+            toml::from_str(&contents)?
+        };
+        ensure!(!custom.subset.is_empty(), "No subsets defined in '{}'.", path.display());
+
+        let mut names = HashSet::new();
+        let mut subsets = Vec::new();
+        for subset in custom.subset {
+            ensure!(!subset.name.is_empty(), "Subset names cannot be empty.");
+            ensure!(names.insert(subset.name.clone()), "Duplicate subset name: {}", subset.name);
+
+            let mut map = CharacterSet::new();
+            for range in &subset.ranges {
+                map.extend(parse_unicode_range(range)?);
+            }
+            map.extend(subset.chars.chars());
+            ensure!(!map.is_empty(), "Subset {} contains no characters.", subset.name);
+            subsets.push(Arc::new(WebfontSubset { name: subset.name.into(), map }));
+        }
+
+        let by_name = build_by_name(&subsets);
+        Ok(WebfontData { by_name, subsets, groups: Vec::new() })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CustomSubset {
+    name: String,
+    #[serde(default)]
+    ranges: Vec<String>,
+    #[serde(default)]
+    chars: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CustomSubsets {
+    subset: Vec<CustomSubset>,
+}
+
+/// Parses a single `unicode-range` value, such as `U+0025-00FF`, `U+4??` or `U+20AC`.
+fn parse_unicode_range(range: &str) -> Result<std::ops::RangeInclusive<u32>> {
+    let Some(spec) = range.trim().strip_prefix("U+") else {
+        bail!("Unicode range does not start with `U+`: {range}");
+    };
+    let parse = |x: &str| {
+        u32::from_str_radix(x, 16).with_context(|| format!("Invalid unicode range: {range}"))
+    };
+    let (start, end) = if let Some((start, end)) = spec.split_once('-') {
+        (parse(start)?, parse(end)?)
+    } else if spec.contains('?') {
+        (parse(&spec.replace('?', "0"))?, parse(&spec.replace('?', "F"))?)
+    } else {
+        let val = parse(spec)?;
+        (val, val)
+    };
+    ensure!(start <= end && end <= 0x10FFFF, "Invalid unicode range: {range}");
+    Ok(start..=end)
 }
 
 #[derive(Clone, Debug)]
@@ -76,10 +163,12 @@ fn split_groups(
             .or_default()
             .push(group);
     }
-    let groups: Vec<_> = groups
+    let mut groups: Vec<_> = groups
         .into_iter()
         .map(|(k, v)| Arc::new(WebfontSubsetGroup { name: k.into(), subsets: v }))
         .collect();
+    // sort so that the splitter does not depend on `HashMap` iteration order
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
     (no_group, groups)
 }
 
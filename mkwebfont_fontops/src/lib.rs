@@ -1,3 +1,9 @@
 pub mod font_info;
 pub mod gfonts;
 pub mod subsetter;
+
+#[cfg(test)]
+mod tests {
+    /// Path for Noto Sans font.
+    pub(crate) const NOTO_SANS: &str = "../mkwebfont_hb-subset/tests/fonts/NotoSans.ttf";
+}
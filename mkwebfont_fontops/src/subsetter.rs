@@ -1,12 +1,24 @@
-use crate::font_info::{FontFaceWrapper, FontStyle, FontWeight};
+use crate::font_info::{
+    AxisName, FontFaceWrapper, FontMetrics, FontStyle, FontWeight, SubsetSettings,
+};
 use anyhow::*;
 use mkwebfont_common::{
     character_set::CharacterSet,
-    hashing::{hash_fragment, hash_full},
+    hashing::{hash_fragment, hash_full, integrity_hash},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    ops::RangeInclusive,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+    thread,
 };
-use std::{fs, ops::RangeInclusive, path::Path, sync::Arc};
-use tokio::{task, task::JoinHandle};
-use tracing::{debug, Instrument};
+use tokio::{sync::Semaphore, task, task::JoinHandle};
+use tracing::{debug, warn, Instrument};
 use unicode_blocks::find_unicode_block;
 
 fn extract_name(str: &str) -> String {
@@ -40,6 +52,69 @@ fn extract_version(mut str: &str) -> String {
     out.trim_matches('.').to_string()
 }
 
+const TEMPLATE_PLACEHOLDERS: &[&str] = &["family", "style", "weight", "version", "subset", "hash"];
+
+/// Checks that a file name template is valid.
+///
+/// The following placeholders are supported:
+///
+/// * `{family}` - The font family, with only alphanumeric characters kept.
+/// * `{style}` - The font style name, with only alphanumeric characters kept.
+/// * `{weight}` - The weight of the font, or a range such as `100-900` for variable fonts.
+/// * `{version}` - The font version.
+/// * `{subset}` - The name of the subset.
+/// * `{hash}` - A hash of the contents of all subsets of the font, or only of the subset itself if
+///   [`SubsetSettings::per_subset_hashing`] is set.
+///
+/// Both `{subset}` and `{hash}` are required, so that file names never collide.
+pub fn validate_file_name_template(template: &str) -> Result<()> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Unclosed placeholder in file name template: {template:?}");
+        };
+        let name = &rest[start + 1..start + end];
+        ensure!(
+            TEMPLATE_PLACEHOLDERS.contains(&name),
+            "Unknown placeholder `{{{name}}}` in file name template: {template:?}",
+        );
+        found.push(name);
+        rest = &rest[start + end + 1..];
+    }
+    ensure!(!rest.contains('}'), "Unopened placeholder in file name template: {template:?}");
+    ensure!(
+        !template.contains('/') && !template.contains('\\'),
+        "File name templates may not contain path separators: {template:?}",
+    );
+    ensure!(
+        found.contains(&"subset") && found.contains(&"hash"),
+        "File name templates must contain both `{{subset}}` and `{{hash}}`: {template:?}",
+    );
+    Ok(())
+}
+
+fn render_file_name_template(
+    template: &str,
+    font: &FontFaceWrapper,
+    subset: &str,
+    hash: &str,
+) -> String {
+    let weight = font.weight_range();
+    let weight = if weight.start() == weight.end() {
+        weight.start().to_string()
+    } else {
+        format!("{}-{}", weight.start(), weight.end())
+    };
+    template
+        .replace("{family}", &extract_name(font.font_family()))
+        .replace("{style}", &extract_name(font.font_style()))
+        .replace("{weight}", &weight)
+        .replace("{version}", &extract_version(font.font_version()))
+        .replace("{subset}", subset)
+        .replace("{hash}", hash)
+}
+
 fn is_same_block(ch_a: char, ch_b: char) -> bool {
     if let Some(block_a) = find_unicode_block(ch_a) {
         if let Some(block_b) = find_unicode_block(ch_b) {
@@ -51,37 +126,41 @@ fn is_same_block(ch_a: char, ch_b: char) -> bool {
 
 fn decode_range(bitmap: &CharacterSet, all_chars: &CharacterSet) -> Vec<RangeInclusive<u32>> {
     let mut range_start = None;
-    let mut range_last = '\u{fffff}';
+    let mut range_last = 0;
     let mut ranges = Vec::new();
 
+    // codepoints are compared as integers, as the codepoint after the end of a range may be a
+    // surrogate or lie past U+10FFFF, neither of which is a valid `char`
     for ch in bitmap.iter_sorted() {
         let ch = char::from_u32(ch).expect("Invalid char in RoaringBitmap");
         if let Some(start) = range_start {
-            let next = char::from_u32(range_last as u32 + 1).unwrap();
-            if next != ch {
+            let next = range_last + 1;
+            if next != ch as u32 {
                 let mut can_merge = false;
-                if is_same_block(next, ch) {
-                    can_merge = true;
-                    for ch in next..ch {
-                        if all_chars.contains(ch as u32) {
-                            can_merge = false;
-                            break;
+                if let Some(next) = char::from_u32(next) {
+                    if is_same_block(next, ch) {
+                        can_merge = true;
+                        for ch in next..ch {
+                            if all_chars.contains(ch as u32) {
+                                can_merge = false;
+                                break;
+                            }
                         }
                     }
                 }
 
                 if !can_merge {
-                    ranges.push(start as u32..=range_last as u32);
-                    range_start = Some(ch);
+                    ranges.push(start..=range_last);
+                    range_start = Some(ch as u32);
                 }
             }
         } else {
-            range_start = Some(ch);
+            range_start = Some(ch as u32);
         }
-        range_last = ch;
+        range_last = ch as u32;
     }
     if let Some(start) = range_start {
-        ranges.push(start as u32..=range_last as u32);
+        ranges.push(start..=range_last);
     }
 
     ranges
@@ -95,17 +174,23 @@ pub struct WebfontInfo {
     font_style: FontStyle,
     font_weight: FontWeight,
     weight_range: RangeInclusive<u32>,
+    width_range: Option<RangeInclusive<f32>>,
+    slant_range: Option<RangeInclusive<f32>>,
+    metrics: Option<FontMetrics>,
     entries: Vec<Arc<SubsetInfo>>,
 }
 impl WebfontInfo {
     /// Writes the webfont files to the given directory.
     pub fn write_to_store(&self, target: &Path) -> Result<()> {
         let mut path = target.to_path_buf();
-        for entry in &self.entries {
-            path.push(&entry.woff2_file_name);
+        for (file_name, data) in self.entries.iter().flat_map(|x| x.files()) {
+            path.push(file_name);
             debug!("Writing {}...", path.display());
-            fs::write(&path, &entry.woff2_data)?;
-            path.pop();
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, data)?;
+            path = target.to_path_buf();
         }
         Ok(())
     }
@@ -137,6 +222,21 @@ impl WebfontInfo {
         self.weight_range.clone()
     }
 
+    /// Returns the range of the width axis, if it was kept variable in the subsets.
+    pub fn width_range(&self) -> Option<RangeInclusive<f32>> {
+        self.width_range.clone()
+    }
+
+    /// Returns the range of the slant axis, if it was kept variable in the subsets.
+    pub fn slant_range(&self) -> Option<RangeInclusive<f32>> {
+        self.slant_range.clone()
+    }
+
+    /// Returns the vertical metrics of the original font, shared by all subsets.
+    pub fn metrics(&self) -> Option<FontMetrics> {
+        self.metrics
+    }
+
     /// Returns the number of subsets in the webfont.
     pub fn subset_count(&self) -> usize {
         self.entries.len()
@@ -164,6 +264,11 @@ pub struct SubsetInfo {
     subset: CharacterSet,
     subset_ranges: Vec<RangeInclusive<u32>>,
     woff2_data: Vec<u8>,
+    woff_file_name: Option<String>,
+    woff_data: Option<Vec<u8>>,
+    integrity: String,
+    woff_integrity: Option<String>,
+    is_base: bool,
 }
 impl SubsetInfo {
     fn new(
@@ -171,6 +276,7 @@ impl SubsetInfo {
         name: &str,
         subset: CharacterSet,
         woff2_data: Vec<u8>,
+        woff_data: Option<Vec<u8>>,
         range_exclusions: &CharacterSet,
     ) -> Self {
         let font_name = extract_name(font.font_family());
@@ -195,12 +301,31 @@ impl SubsetInfo {
             ),
             subset,
             subset_ranges,
+            integrity: integrity_hash(&woff2_data),
+            woff_integrity: woff_data.as_deref().map(integrity_hash),
             woff2_data,
+            woff_file_name: None,
+            woff_data,
+            is_base: false,
         }
     }
 
-    fn finalize_name(&mut self, frag: &str) {
-        self.woff2_file_name = format!("{}_{frag}.woff2", self.woff2_file_name);
+    fn finalize_name(&mut self, font: &FontFaceWrapper, frag: &str, settings: &SubsetSettings) {
+        let file_name = match &settings.file_name_template {
+            Some(template) => render_file_name_template(template, font, &self.name, frag),
+            None => format!("{}_{frag}.woff2", self.woff2_file_name),
+        };
+        self.woff2_file_name = if settings.nested_store {
+            format!("{}/{file_name}", extract_name(font.font_family()))
+        } else {
+            file_name
+        };
+        if self.woff_data.is_some() {
+            self.woff_file_name = Some(match self.woff2_file_name.strip_suffix(".woff2") {
+                Some(stem) => format!("{stem}.woff"),
+                None => format!("{}.woff", self.woff2_file_name),
+            });
+        }
     }
 
     /// Returns the name of the subset.
@@ -227,51 +352,210 @@ impl SubsetInfo {
     pub fn woff2_data(&self) -> &[u8] {
         &self.woff2_data
     }
+
+    /// Returns the file name that the .woff version of this subset will be saved to, if one was
+    /// generated.
+    pub fn woff_file_name(&self) -> Option<&str> {
+        self.woff_file_name.as_deref()
+    }
+
+    /// Returns the .woff data as an array, if it was generated.
+    pub fn woff_data(&self) -> Option<&[u8]> {
+        self.woff_data.as_deref()
+    }
+
+    /// Returns the subresource integrity hash of the .woff2 file, such as `sha384-...`.
+    pub fn integrity(&self) -> &str {
+        &self.integrity
+    }
+
+    /// Returns the subresource integrity hash of the .woff file, if it was generated.
+    pub fn woff_integrity(&self) -> Option<&str> {
+        self.woff_integrity.as_deref()
+    }
+
+    /// Returns whether this is the base subset of its font, which contains its most commonly used
+    /// characters and is worth preloading.
+    pub fn is_base(&self) -> bool {
+        self.is_base
+    }
+
+    /// Returns every file generated for this subset, as pairs of file names and contents.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        let woff = match (&self.woff_file_name, &self.woff_data) {
+            (Some(name), Some(data)) => Some((name.as_str(), data.as_slice())),
+            _ => None,
+        };
+        std::iter::once((self.woff2_file_name.as_str(), self.woff2_data.as_slice())).chain(woff)
+    }
+}
+
+/// Makes subsets whose .woff2 data is byte-identical share a single file, so each unique file is
+/// only written and downloaded once. Returns the number of subsets that now share another subset's
+/// file.
+///
+/// The first subset with given contents keeps its file name, and later subsets are pointed at it.
+pub fn dedup_subset_files(webfonts: &mut [Arc<WebfontInfo>]) -> usize {
+    let mut files: HashMap<String, (String, Option<String>)> = HashMap::new();
+    let mut shared = 0;
+    for webfont in webfonts {
+        for subset in &mut Arc::make_mut(webfont).entries {
+            let hash = hash_full(&subset.woff2_data);
+            match files.get(&hash) {
+                Some((woff2_name, _)) if *woff2_name == subset.woff2_file_name => {}
+                Some((woff2_name, woff_name)) => {
+                    debug!("Subset '{}' is identical to '{woff2_name}'.", subset.woff2_file_name);
+                    let subset = Arc::make_mut(subset);
+                    subset.woff2_file_name = woff2_name.clone();
+                    if subset.woff_file_name.is_some() && woff_name.is_some() {
+                        subset.woff_file_name = woff_name.clone();
+                    }
+                    shared += 1;
+                }
+                None => {
+                    let names = (subset.woff2_file_name.clone(), subset.woff_file_name.clone());
+                    files.insert(hash, names);
+                }
+            }
+        }
+    }
+    shared
+}
+
+/// The maximum number of subsets encoded at once, or 0 to use the number of CPUs.
+static ENCODE_CONCURRENCY: AtomicUsize = AtomicUsize::new(0);
+static ENCODE_PERMITS: LazyLock<Semaphore> = LazyLock::new(|| {
+    let limit = match ENCODE_CONCURRENCY.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |x| x.get()),
+        limit => limit,
+    };
+    Semaphore::new(limit)
+});
+
+/// Sets the maximum number of subsets that are encoded at once. Defaults to the number of CPUs.
+///
+/// Each subset being encoded holds its uncompressed font data and Brotli state in memory, so
+/// lowering this reduces peak memory use on large fonts. This must be called before any subset
+/// is encoded to have an effect.
+pub fn set_encode_concurrency(limit: usize) {
+    ENCODE_CONCURRENCY.store(limit.max(1), Ordering::Relaxed);
 }
 
 pub struct FontEncoder {
     font: FontFaceWrapper,
+    settings: Arc<SubsetSettings>,
     woff2_subsets: Vec<JoinHandle<Result<SubsetInfo>>>,
     range_exclusion: Arc<CharacterSet>,
+    used_names: HashSet<String>,
 }
 impl FontEncoder {
-    pub fn new(font: FontFaceWrapper, range_exclusion: CharacterSet) -> Self {
+    pub fn new(
+        font: FontFaceWrapper,
+        range_exclusion: CharacterSet,
+        settings: SubsetSettings,
+    ) -> Self {
         let range_exclusion = Arc::new(range_exclusion);
-        FontEncoder { font, woff2_subsets: Vec::new(), range_exclusion }
+        let settings = Arc::new(settings);
+        FontEncoder {
+            font,
+            settings,
+            woff2_subsets: Vec::new(),
+            range_exclusion,
+            used_names: HashSet::new(),
+        }
+    }
+
+    /// Returns a subset name that has not yet been used in this encoder, as file names are
+    /// derived from subset names and a collision would overwrite another subset.
+    fn unique_name(&mut self, name: &str) -> String {
+        let mut new_name = name.to_string();
+        let mut idx = 1;
+        while !self.used_names.insert(new_name.clone()) {
+            idx += 1;
+            new_name = format!("{name}{idx}");
+        }
+        if new_name != name {
+            warn!("Duplicate subset name '{name}' in {}, renaming to '{new_name}'.", self.font);
+        }
+        new_name
     }
 
     pub fn add_subset(&mut self, name: &str, codepoints: CharacterSet) {
-        let name = name.to_string();
+        self.push_subset(name, codepoints, false);
+    }
+
+    /// Adds the base subset of the font, which contains its most commonly used characters and is
+    /// worth preloading. See [`SubsetInfo::is_base`].
+    pub fn add_base_subset(&mut self, name: &str, codepoints: CharacterSet) {
+        self.push_subset(name, codepoints, true);
+    }
+
+    fn push_subset(&mut self, name: &str, codepoints: CharacterSet, is_base: bool) {
+        let name = self.unique_name(name);
         let font = self.font.clone();
         let range_exclusion = self.range_exclusion.clone();
+        let settings = self.settings.clone();
         self.woff2_subsets.push(task::spawn(
             async move {
-                debug!("Encoding subset '{name}' with {} codepoints.", codepoints.len());
-                let subset_woff2 = font.subset(&name, &codepoints)?;
-                Ok(SubsetInfo::new(&font, &name, codepoints, subset_woff2, &range_exclusion))
+                let _permit = ENCODE_PERMITS.acquire().await?;
+                let span = tracing::Span::current();
+                task::spawn_blocking(move || {
+                    let _enter = span.enter();
+                    debug!("Encoding subset '{name}' with {} codepoints.", codepoints.len());
+                    let (woff2, woff) = font.subset_woff(&name, &codepoints, &settings)?;
+                    let mut info =
+                        SubsetInfo::new(&font, &name, codepoints, woff2, woff, &range_exclusion);
+                    info.is_base = is_base;
+                    Ok(info)
+                })
+                .await?
             }
             .in_current_span(),
         ));
     }
 
+    /// Returns the weight range of the subsets, which is the default weight of the font if its
+    /// weight axis is pinned.
+    fn weight_range(&self) -> RangeInclusive<u32> {
+        if !self.settings.keeps_axis(Some(AxisName::Weight)) {
+            if let Some(axis) = self
+                .font
+                .variations()
+                .iter()
+                .find(|x| x.axis == Some(AxisName::Weight))
+            {
+                let weight = axis.default as u32;
+                return weight..=weight;
+            }
+        }
+        self.font.weight_range()
+    }
+
     pub async fn produce_webfont(self) -> Result<WebfontInfo> {
+        let weight_range = self.weight_range();
         let mut entries = Vec::new();
         for data in self.woff2_subsets {
             entries.push(data.await??);
         }
         entries.sort_by_cached_key(|x| x.woff2_file_name.to_string());
 
-        let fragment = {
+        let fragment = if self.settings.per_subset_hashing {
+            None
+        } else {
             let mut data = Vec::new();
             for entry in &entries {
                 data.extend(hash_full(&entry.woff2_data).as_bytes());
             }
-            hash_fragment(&data)
+            Some(hash_fragment(&data))
         };
         let entries: Vec<_> = entries
             .into_iter()
             .map(|mut x| {
-                x.finalize_name(&fragment);
+                let fragment = match &fragment {
+                    Some(fragment) => fragment.clone(),
+                    None => hash_fragment(&x.woff2_data),
+                };
+                x.finalize_name(&self.font, &fragment, &self.settings);
                 Arc::new(x)
             })
             .collect();
@@ -281,8 +565,61 @@ impl FontEncoder {
             font_style_text: self.font.font_style().to_string().into(),
             font_style: self.font.parsed_font_style(),
             font_weight: self.font.parsed_font_weight(),
-            weight_range: self.font.weight_range(),
+            weight_range,
+            width_range: self
+                .settings
+                .keeps_axis(Some(AxisName::Width))
+                .then(|| self.font.axis_range(AxisName::Width))
+                .flatten(),
+            slant_range: self
+                .settings
+                .keeps_axis(Some(AxisName::Slant))
+                .then(|| self.font.axis_range(AxisName::Slant))
+                .flatten(),
+            metrics: self.font.metrics(),
             entries,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::NOTO_SANS;
+
+    #[test]
+    fn duplicate_subset_names_are_suffixed() {
+        let font = FontFaceWrapper::load(None, fs::read(NOTO_SANS).unwrap())
+            .unwrap()
+            .remove(0);
+        let mut encoder = FontEncoder::new(font, CharacterSet::new(), SubsetSettings::default());
+        assert_eq!(encoder.unique_name("misc1"), "misc1");
+        assert_eq!(encoder.unique_name("misc1"), "misc12");
+        assert_eq!(encoder.unique_name("misc1"), "misc13");
+    }
+
+    #[test]
+    fn decode_range_handles_emoji() {
+        let emoji: CharacterSet = (0x1F600..=0x1F64F).collect();
+        assert_eq!(decode_range(&emoji, &emoji), vec![0x1F600..=0x1F64F]);
+
+        let bitmap: CharacterSet = [0x1F600, 0x1F602].into_iter().collect();
+        let all_chars: CharacterSet = (0x1F600..=0x1F602).collect();
+        assert_eq!(decode_range(&bitmap, &all_chars), vec![0x1F600..=0x1F600, 0x1F602..=0x1F602]);
+    }
+
+    #[test]
+    fn decode_range_handles_range_before_surrogates() {
+        let bitmap: CharacterSet = (0xD7F0..=0xD7FF).chain([0xE000]).collect();
+        assert_eq!(decode_range(&bitmap, &bitmap), vec![0xD7F0..=0xD7FF, 0xE000..=0xE000]);
+    }
+
+    #[test]
+    fn decode_range_handles_last_codepoint() {
+        let bitmap: CharacterSet = (0x10FFF0..=0x10FFFF).collect();
+        assert_eq!(decode_range(&bitmap, &bitmap), vec![0x10FFF0..=0x10FFFF]);
+
+        let bitmap: CharacterSet = [0x10FFF0, 0x10FFFF].into_iter().collect();
+        assert_eq!(decode_range(&bitmap, &bitmap), vec![0x10FFF0..=0x10FFFF]);
+    }
+}